@@ -0,0 +1,31 @@
+use super::Family;
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Asks an echo service for our address. The connection is bound to the
+/// unspecified local address of the family, which forces it.
+pub fn fetch(url: &str, family: Family) -> Result<IpAddr, Box<dyn Error>> {
+    let local: IpAddr = match family {
+        Family::V4 => Ipv4Addr::UNSPECIFIED.into(),
+        Family::V6 => Ipv6Addr::UNSPECIFIED.into(),
+    };
+
+    let resp = reqwest::blocking::ClientBuilder::default()
+        .local_address(local)
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let resp = resp.trim();
+    resp.parse().map_err(|_| {
+        format!(
+            "invalid address {:?}",
+            resp.chars().take(40).collect::<String>()
+        )
+        .into()
+    })
+}
@@ -0,0 +1,146 @@
+use super::Family;
+use std::{
+    collections::HashSet,
+    error::Error,
+    ffi::CStr,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ptr,
+};
+
+/// `IFA_F_*` flags of IPv6 addresses that must not be published.
+const IFA_F_TEMPORARY: u32 = 0x01;
+const IFA_F_DADFAILED: u32 = 0x08;
+const IFA_F_DEPRECATED: u32 = 0x20;
+const IFA_F_TENTATIVE: u32 = 0x40;
+
+/// Lists the addresses bound to local interfaces, with their interface name.
+fn addresses() -> Result<Vec<(String, IpAddr)>, Box<dyn Error>> {
+    let mut found = vec![];
+
+    let mut ifaddrs = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let mut ifa = ifaddrs;
+    while !ifa.is_null() {
+        // SAFETY: getifaddrs returned a valid list, freed only below
+        let entry = unsafe { &*ifa };
+        ifa = entry.ifa_next;
+
+        if entry.ifa_addr.is_null() {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+
+        match unsafe { (*entry.ifa_addr).sa_family } as i32 {
+            libc::AF_INET => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                found.push((name, ip.into()));
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in6) };
+                found.push((name, Ipv6Addr::from(addr.sin6_addr.s6_addr).into()));
+            }
+            _ => {}
+        }
+    }
+
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(found)
+}
+
+/// IPv6 addresses that are temporary, deprecated or not yet usable,
+/// according to the kernel. Empty where `/proc/net/if_inet6` is missing.
+fn unstable_ipv6() -> HashSet<Ipv6Addr> {
+    let table = fs::read_to_string("/proc/net/if_inet6").unwrap_or_default();
+
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = u32::from_str_radix(fields.get(4)?, 16).ok()?;
+            let ip = u128::from_str_radix(fields.first()?, 16).ok()?;
+            (flags & (IFA_F_TEMPORARY | IFA_F_DADFAILED | IFA_F_DEPRECATED | IFA_F_TENTATIVE) != 0)
+                .then(|| Ipv6Addr::from(ip))
+        })
+        .collect()
+}
+
+/// Whether the address is reachable from the internet: not private,
+/// shared, link-local, unique local or loopback.
+pub fn is_global(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_link_local()
+                || ip.is_loopback()
+                || ip.is_unspecified()
+                // 100.64.0.0/10, carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                // fe80::/10, link-local
+                || first & 0xffc0 == 0xfe80
+                // fc00::/7, unique local
+                || first & 0xfe00 == 0xfc00)
+        }
+    }
+}
+
+/// Returns the first public address of the family bound to one of
+/// `interfaces`, or to any interface when the list is empty.
+pub fn fetch(interfaces: &[String], family: Family) -> Result<IpAddr, Box<dyn Error>> {
+    let unstable = unstable_ipv6();
+
+    addresses()?
+        .into_iter()
+        .filter(|(name, _)| interfaces.is_empty() || interfaces.contains(name))
+        .map(|(_, ip)| ip)
+        .find(|ip| {
+            family.accepts(ip)
+                && is_global(ip)
+                && !matches!(ip, IpAddr::V6(ip) if unstable.contains(ip))
+        })
+        .ok_or_else(|| format!("no public {family} address on the interfaces").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_addresses() {
+        for ip in ["203.0.113.7", "100.128.0.1", "2001:db8::1", "2a00::1"] {
+            assert!(is_global(&ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn non_global_addresses() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "100.127.255.254",
+            "169.254.1.1",
+            "127.0.0.1",
+            "0.0.0.0",
+            "fe80::1",
+            "fd00::1",
+            "::1",
+            "::",
+        ] {
+            assert!(!is_global(&ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
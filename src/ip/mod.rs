@@ -0,0 +1,252 @@
+mod http;
+mod interface;
+mod natpmp;
+mod porkbun;
+mod upnp;
+
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Public addresses of this host, detected separately per family.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Addresses {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl Addresses {
+    /// Returns the address matching a DNS record type (`A` or `AAAA`), if
+    /// one was detected.
+    pub fn for_record(&self, record_type: &str) -> Option<IpAddr> {
+        match record_type {
+            "A" => self.ipv4.map(IpAddr::V4),
+            "AAAA" => self.ipv6.map(IpAddr::V6),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Addresses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ips: Vec<String> = [self.for_record("A"), self.for_record("AAAA")]
+            .iter()
+            .flatten()
+            .map(|ip| ip.to_string())
+            .collect();
+        write!(f, "{}", ips.join(", "))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    /// Whether `ip` is a usable public address of this family.
+    fn accepts(self, ip: &IpAddr) -> bool {
+        let family = match ip {
+            IpAddr::V4(_) => Family::V4,
+            IpAddr::V6(_) => Family::V6,
+        };
+        family == self && !ip.is_unspecified() && !ip.is_loopback() && !ip.is_multicast()
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Family::V4 => write!(f, "IPv4"),
+            Family::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// Somewhere the public address can be read from. In config files it is the
+/// name of a well-known echo service, the URL of any service answering with
+/// the bare address, one of the gateway protocols `upnp`, `natpmp` and
+/// `pcp`, `interface` for the addresses bound to this host, or `porkbun` for
+/// the ping endpoint of the Porkbun API.
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+pub enum Source {
+    Http(String),
+    /// WAN address of the UPnP Internet Gateway Device.
+    Upnp,
+    NatPmp,
+    Pcp,
+    Interface,
+    Porkbun,
+}
+
+impl TryFrom<String> for Source {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Ok(match name.as_str() {
+            "ipify" => Source::Http("https://api64.ipify.org".to_string()),
+            "icanhazip" => Source::Http("https://icanhazip.com".to_string()),
+            "ifconfig.co" => Source::Http("https://ifconfig.co/ip".to_string()),
+            "myexternalip" => Source::Http("https://myexternalip.com/raw".to_string()),
+            "upnp" => Source::Upnp,
+            "natpmp" => Source::NatPmp,
+            "pcp" => Source::Pcp,
+            "interface" => Source::Interface,
+            "porkbun" => Source::Porkbun,
+            url if url.starts_with("https://") || url.starts_with("http://") => Source::Http(name),
+            _ => return Err(format!("unknown IP source {name}")),
+        })
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Http(url) => write!(f, "{url}"),
+            Source::Upnp => write!(f, "upnp"),
+            Source::NatPmp => write!(f, "natpmp"),
+            Source::Pcp => write!(f, "pcp"),
+            Source::Interface => write!(f, "interface"),
+            Source::Porkbun => write!(f, "porkbun"),
+        }
+    }
+}
+
+impl Source {
+    /// Asks the source for our address of the given family. Returns `None`
+    /// when the source cannot provide addresses of that family.
+    fn fetch(&self, family: Family, config: &IpConfig) -> Result<Option<IpAddr>, Box<dyn Error>> {
+        let gateway = || match config.gateway {
+            Some(gateway) => Ok(gateway),
+            None => natpmp::default_gateway(),
+        };
+
+        let ip = match (self, family) {
+            (Source::Http(url), _) => http::fetch(url, family)?,
+            (Source::Interface, _) => interface::fetch(&config.interfaces, family)?,
+            (Source::Porkbun, _) => porkbun::fetch(
+                &config.porkbun_api_key,
+                &config.porkbun_secret_api_key,
+                family,
+            )?,
+            // Gateways only translate IPv4
+            (_, Family::V6) => return Ok(None),
+            (Source::Upnp, Family::V4) => upnp::fetch(&config.ssdp)?,
+            (Source::NatPmp, Family::V4) => natpmp::fetch_natpmp(gateway()?)?,
+            (Source::Pcp, Family::V4) => natpmp::fetch_pcp(gateway()?)?,
+        };
+
+        // Gateways behind carrier-grade or double NAT report a shared or
+        // private WAN address
+        let from_gateway = matches!(self, Source::Upnp | Source::NatPmp | Source::Pcp);
+        match family.accepts(&ip) && (!from_gateway || interface::is_global(&ip)) {
+            true => Ok(Some(ip)),
+            false => Err(format!("{ip} is not a public {family} address").into()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct IpConfig {
+    /// Sources asked in order, until `quorum` of them agree.
+    sources: Vec<Source>,
+    /// How many sources must return the same address before it is used.
+    quorum: usize,
+    ipv4: bool,
+    ipv6: bool,
+    /// Gateway asked by the `natpmp` and `pcp` sources, the default route
+    /// when unset.
+    gateway: Option<Ipv4Addr>,
+    /// Where the `upnp` source sends its SSDP search.
+    ssdp: String,
+    /// Interfaces read by the `interface` source, all of them when empty.
+    interfaces: Vec<String>,
+    /// API keys sent by the `porkbun` source.
+    porkbun_api_key: Option<String>,
+    porkbun_secret_api_key: Option<String>,
+}
+
+impl Default for IpConfig {
+    fn default() -> Self {
+        IpConfig {
+            sources: ["ipify", "icanhazip", "ifconfig.co", "myexternalip"]
+                .iter()
+                .map(|name| Source::try_from(name.to_string()).unwrap())
+                .collect(),
+            quorum: 1,
+            ipv4: true,
+            ipv6: true,
+            gateway: None,
+            ssdp: "239.255.255.250:1900".to_string(),
+            interfaces: vec![],
+            porkbun_api_key: None,
+            porkbun_secret_api_key: None,
+        }
+    }
+}
+
+impl IpConfig {
+    /// Asks the sources in order until `quorum` of them agree on an address
+    /// of this family. Sources that fail or answer with anything but an
+    /// address are skipped.
+    fn detect_family(&self, family: Family) -> Option<IpAddr> {
+        let mut votes: Vec<(IpAddr, usize)> = vec![];
+
+        for source in &self.sources {
+            let ip = match source.fetch(family, self) {
+                Ok(Some(ip)) => ip,
+                Ok(None) => continue,
+                Err(e) => {
+                    println!("{family} source {source}: {e}");
+                    continue;
+                }
+            };
+
+            let count = match votes.iter_mut().find(|(vote, _)| *vote == ip) {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count
+                }
+                None => {
+                    votes.push((ip, 1));
+                    1
+                }
+            };
+
+            if count >= self.quorum {
+                return Some(ip);
+            }
+        }
+
+        if !votes.is_empty() {
+            println!("{family} sources did not reach a quorum of {}", self.quorum);
+        }
+        None
+    }
+
+    /// Detects the public addresses of this host, or `None` when neither
+    /// family could be detected.
+    pub fn detect(&self) -> Option<Addresses> {
+        let ips = Addresses {
+            ipv4: match self.ipv4.then(|| self.detect_family(Family::V4)) {
+                Some(Some(IpAddr::V4(ip))) => Some(ip),
+                _ => None,
+            },
+            ipv6: match self.ipv6.then(|| self.detect_family(Family::V6)) {
+                Some(Some(IpAddr::V6(ip))) => Some(ip),
+                _ => None,
+            },
+        };
+
+        match ips.ipv4.is_none() && ips.ipv6.is_none() {
+            true => None,
+            false => Some(ips),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    error::Error,
+    fs,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// NAT-PMP and PCP servers listen on this port of the gateway.
+const PORT: u16 = 5351;
+
+/// Reads the IPv4 default gateway from the kernel routing table.
+pub fn default_gateway() -> Result<Ipv4Addr, Box<dyn Error>> {
+    let routes = fs::read_to_string("/proc/net/route")
+        .map_err(|_| "cannot read the routing table, set ip.gateway")?;
+
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 2 && fields[1] == "00000000" {
+            let gateway = u32::from_str_radix(fields[2], 16)?;
+            return Ok(Ipv4Addr::from(gateway.to_le_bytes()));
+        }
+    }
+
+    Err("no default route, set ip.gateway".into())
+}
+
+/// Sends `request` to the gateway and waits for a response, retrying with
+/// the doubling timeouts of RFC 6886.
+fn exchange(gateway: Ipv4Addr, request: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(SocketAddr::new(gateway.into(), PORT))?;
+
+    let mut timeout = Duration::from_millis(250);
+    let mut buf = [0; 1100];
+    for _ in 0..4 {
+        socket.send(request)?;
+        socket.set_read_timeout(Some(timeout))?;
+        if let Ok(len) = socket.recv(&mut buf) {
+            return Ok(buf[..len].to_vec());
+        }
+        timeout *= 2;
+    }
+
+    Err(format!("no answer from gateway {gateway}").into())
+}
+
+/// Asks the gateway for its external address with a NAT-PMP request.
+pub fn fetch_natpmp(gateway: Ipv4Addr) -> Result<IpAddr, Box<dyn Error>> {
+    let resp = exchange(gateway, &[0, 0])?;
+
+    if resp.len() < 12 || resp[0] != 0 || resp[1] != 128 {
+        return Err("invalid NAT-PMP response".into());
+    }
+    match u16::from_be_bytes([resp[2], resp[3]]) {
+        0 => Ok(Ipv4Addr::new(resp[8], resp[9], resp[10], resp[11]).into()),
+        code => Err(format!("NAT-PMP result code {code}").into()),
+    }
+}
+
+/// Builds a PCP MAP request (RFC 6887) for a UDP port of `client`.
+fn pcp_map_request(client: Ipv4Addr, port: u16, lifetime: u32, nonce: &[u8; 12]) -> Vec<u8> {
+    let mut request = vec![2, 1, 0, 0];
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    request.extend_from_slice(&client.to_ipv6_mapped().octets());
+    request.extend_from_slice(nonce);
+    request.extend_from_slice(&[17, 0, 0, 0]);
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&Ipv6Addr::UNSPECIFIED.octets());
+    request
+}
+
+/// Learns the external address of the gateway from the short-lived mapping
+/// it assigns to a PCP MAP request. The mapping is deleted afterwards.
+pub fn fetch_pcp(gateway: Ipv4Addr) -> Result<IpAddr, Box<dyn Error>> {
+    // The client address must be the one the gateway sees us from
+    let probe = UdpSocket::bind("0.0.0.0:0")?;
+    probe.connect(SocketAddr::new(gateway.into(), PORT))?;
+    let (client, port) = match probe.local_addr()? {
+        SocketAddr::V4(addr) => (*addr.ip(), addr.port()),
+        SocketAddr::V6(_) => return Err("no IPv4 route to the gateway".into()),
+    };
+
+    let nonce: [u8; 12] = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_nanos()
+        .to_be_bytes()[4..]
+        .try_into()?;
+
+    let resp = exchange(gateway, &pcp_map_request(client, port, 30, &nonce))?;
+    // Best effort, the mapping expires on its own anyway
+    let _ = exchange(gateway, &pcp_map_request(client, port, 0, &nonce));
+
+    if resp.len() < 60 || resp[0] != 2 || resp[1] != 0x81 {
+        return Err("invalid PCP response".into());
+    }
+    if resp[3] != 0 {
+        return Err(format!("PCP result code {}", resp[3]).into());
+    }
+
+    let mut ip = [0; 16];
+    ip.copy_from_slice(&resp[44..60]);
+    let ip = Ipv6Addr::from(ip);
+    Ok(match ip.to_ipv4_mapped() {
+        Some(ip) => ip.into(),
+        None => ip.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcp_map_request_layout() {
+        let nonce = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let request = pcp_map_request(Ipv4Addr::new(192, 168, 1, 10), 40000, 30, &nonce);
+
+        // 24 bytes of common header, 36 of MAP opcode data
+        assert_eq!(request.len(), 60);
+        // Version 2, request MAP, reserved
+        assert_eq!(request[..4], [2, 1, 0, 0]);
+        assert_eq!(request[4..8], 30u32.to_be_bytes());
+        assert_eq!(
+            request[8..24],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 192, 168, 1, 10]
+        );
+        assert_eq!(request[24..36], nonce);
+        // UDP, reserved
+        assert_eq!(request[36..40], [17, 0, 0, 0]);
+        assert_eq!(request[40..42], 40000u16.to_be_bytes());
+        assert_eq!(request[42..44], 40000u16.to_be_bytes());
+        assert_eq!(request[44..60], [0; 16]);
+    }
+}
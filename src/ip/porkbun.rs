@@ -0,0 +1,48 @@
+use super::Family;
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Asks the Porkbun `ping` endpoint for our address. It needs API keys, but
+/// no domain to be registered there. The connection is bound like the HTTP
+/// sources to force the family.
+pub fn fetch(
+    api_key: &Option<String>,
+    secret_api_key: &Option<String>,
+    family: Family,
+) -> Result<IpAddr, Box<dyn Error>> {
+    let (api_key, secret_api_key) = match (api_key, secret_api_key) {
+        (Some(api_key), Some(secret_api_key)) => (api_key, secret_api_key),
+        _ => return Err("porkbun_api_key and porkbun_secret_api_key are not set".into()),
+    };
+
+    let local: IpAddr = match family {
+        Family::V4 => Ipv4Addr::UNSPECIFIED.into(),
+        Family::V6 => Ipv6Addr::UNSPECIFIED.into(),
+    };
+
+    let resp = reqwest::blocking::ClientBuilder::default()
+        .local_address(local)
+        .build()?
+        .post("https://api.porkbun.com/api/json/v3/ping")
+        .header("Content-Type", "application/json")
+        .body(
+            json::object! {
+                apikey: api_key.as_str(),
+                secretapikey: secret_api_key.as_str(),
+            }
+            .dump(),
+        )
+        .send()?
+        .text()?;
+
+    let resp = json::parse(&resp)?;
+    if resp["status"] != "SUCCESS" {
+        return Err(format!("{}", resp["message"]).into());
+    }
+
+    let ip = resp["yourIp"].to_string();
+    ip.parse()
+        .map_err(|_| format!("invalid address {ip:?}").into())
+}
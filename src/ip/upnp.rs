@@ -0,0 +1,242 @@
+use crate::xml;
+use std::{
+    error::Error,
+    net::{IpAddr, UdpSocket},
+    time::Duration,
+};
+
+const SEARCH_TARGETS: [&str; 2] = [
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:2",
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+];
+
+const WAN_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// Finds the Internet Gateway Device with an SSDP search sent to `ssdp`
+/// and returns the URL of its device description.
+fn discover(ssdp: &str) -> Result<String, Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    for target in SEARCH_TARGETS {
+        let request = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {target}\r\n\r\n"
+        );
+        socket.send_to(request.as_bytes(), ssdp)?;
+    }
+
+    let mut buf = [0; 2048];
+    loop {
+        let len = match socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(_) => return Err("no Internet Gateway Device answered".into()),
+        };
+
+        let reply = String::from_utf8_lossy(&buf[..len]);
+        let header = |wanted: &str| {
+            reply
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case(wanted))
+                .map(|(_, value)| value.trim())
+        };
+
+        // Other devices on the network may answer too, e.g. to ssdp:all
+        // searches of other clients
+        match (header("st"), header("location")) {
+            (Some(st), Some(location)) if SEARCH_TARGETS.contains(&st) => {
+                return Ok(location.to_string())
+            }
+            _ => continue,
+        }
+    }
+}
+
+/// Resolves a control URL from the device description against the
+/// description URL.
+fn resolve(location: &str, base: Option<String>, control: &str) -> Result<String, Box<dyn Error>> {
+    if control.starts_with("http://") || control.starts_with("https://") {
+        return Ok(control.to_string());
+    }
+
+    let base = reqwest::Url::parse(&base.unwrap_or_else(|| location.to_string()))?;
+    Ok(base.join(control)?.to_string())
+}
+
+/// Asks the gateway found through `ssdp` for its WAN address with the
+/// `GetExternalIPAddress` action.
+pub fn fetch(ssdp: &str) -> Result<IpAddr, Box<dyn Error>> {
+    let location = discover(ssdp)?;
+
+    let client = reqwest::blocking::Client::new();
+    let description = client.get(&location).send()?.error_for_status()?.text()?;
+
+    let (service, control) = xml::elements(&description, "service")
+        .into_iter()
+        .find_map(|service| {
+            let service_type = xml::text(service, "serviceType")?;
+            let control = xml::text(service, "controlURL")?;
+            WAN_SERVICES
+                .contains(&service_type.as_str())
+                .then_some((service_type, control))
+        })
+        .ok_or("the gateway has no WAN connection service")?;
+    let control = resolve(&location, xml::text(&description, "URLBase"), &control)?;
+
+    let resp = client
+        .post(control)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header("SOAPAction", format!("\"{service}#GetExternalIPAddress\""))
+        .body(format!(
+            r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:GetExternalIPAddress xmlns:u="{service}"/></s:Body></s:Envelope>"#
+        ))
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let ip =
+        xml::text(&resp, "NewExternalIPAddress").ok_or("no NewExternalIPAddress in response")?;
+    Ok(ip.parse().map_err(|_| format!("invalid address {ip:?}"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    /// Answers `count` HTTP requests with the body routed by path, and
+    /// returns the requests received.
+    fn serve(
+        listener: TcpListener,
+        count: usize,
+        routes: Vec<(&'static str, String)>,
+    ) -> JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut requests = vec![];
+            for _ in 0..count {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 4096];
+                loop {
+                    let len = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..len]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .filter_map(|line| line.split_once(':'))
+                            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                            .map_or(0, |(_, value)| value.trim().parse().unwrap());
+                        if body.len() >= length {
+                            break;
+                        }
+                    }
+                }
+
+                let request = String::from_utf8(request).unwrap();
+                let path = request.split_whitespace().nth(1).unwrap();
+                let body = &routes.iter().find(|(route, _)| *route == path).unwrap().1;
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+                requests.push(request);
+            }
+            requests
+        })
+    }
+
+    #[test]
+    fn external_address() {
+        let http = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", http.local_addr().unwrap());
+        let description = r#"<?xml version="1.0"?><root xmlns="urn:schemas-upnp-org:device-1-0"><device><deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType><serviceList><service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/ctl/L3F</controlURL></service></serviceList><deviceList><device><deviceList><device><serviceList><service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId><controlURL>/ctl/IPConn</controlURL></service></serviceList></device></deviceList></device></deviceList></device></root>"#;
+        let soap = r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1"><NewExternalIPAddress>203.0.113.7</NewExternalIPAddress></u:GetExternalIPAddressResponse></s:Body></s:Envelope>"#;
+        let server = serve(
+            http,
+            2,
+            vec![
+                ("/rootDesc.xml", description.to_string()),
+                ("/ctl/IPConn", soap.to_string()),
+            ],
+        );
+
+        // A printer answering first must not be taken for the gateway
+        let ssdp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ssdp_addr = ssdp.local_addr().unwrap().to_string();
+        let responder = thread::spawn(move || {
+            let mut buf = [0; 2048];
+            let (len, client) = ssdp.recv_from(&mut buf).unwrap();
+            let search = String::from_utf8_lossy(&buf[..len]).to_string();
+            for (st, location) in [
+                (
+                    "urn:schemas-upnp-org:device:Printer:1",
+                    "http://127.0.0.1:9/printer.xml".to_string(),
+                ),
+                (SEARCH_TARGETS[1], format!("{base}/rootDesc.xml")),
+            ] {
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nST: {st}\r\nUSN: uuid:1::{st}\r\nLocation: {location}\r\n\r\n"
+                );
+                ssdp.send_to(reply.as_bytes(), client).unwrap();
+            }
+            search
+        });
+
+        assert_eq!(
+            fetch(&ssdp_addr).unwrap(),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+
+        let search = responder.join().unwrap();
+        assert!(search.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+        assert!(search.contains("MAN: \"ssdp:discover\"\r\n"));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /rootDesc.xml "));
+        assert!(requests[1].starts_with("POST /ctl/IPConn "));
+        assert!(requests[1].to_lowercase().contains(
+            "soapaction: \"urn:schemas-upnp-org:service:wanipconnection:1#getexternalipaddress\""
+        ));
+        assert!(requests[1].contains(
+            "<u:GetExternalIPAddress xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\"/>"
+        ));
+    }
+
+    #[test]
+    fn control_urls() {
+        let location = "http://192.168.1.1:5000/rootDesc.xml";
+        assert_eq!(
+            resolve(location, None, "/ctl/IPConn").unwrap(),
+            "http://192.168.1.1:5000/ctl/IPConn"
+        );
+        assert_eq!(
+            resolve(
+                location,
+                Some("http://192.168.1.1:49152/".to_string()),
+                "ctl/IPConn"
+            )
+            .unwrap(),
+            "http://192.168.1.1:49152/ctl/IPConn"
+        );
+        assert_eq!(
+            resolve(
+                location,
+                None,
+                "http://192.168.1.1:49000/upnp/control/WANIPConn1"
+            )
+            .unwrap(),
+            "http://192.168.1.1:49000/upnp/control/WANIPConn1"
+        );
+    }
+}
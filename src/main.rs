@@ -1,170 +1,232 @@
-use reqwest::{blocking::Client, StatusCode};
+mod ip;
+mod jwt;
+mod services;
+mod state;
+mod xml;
+
+use ip::{Addresses, IpConfig};
 use serde_derive::Deserialize;
+use services::{
+    one_or_many, AfraidService, AzureDnsService, CloudflareService, DesecService,
+    DigitalOceanService, DuckDNSService, Dyndns2Service, GandiService, GcloudDnsService, HeService,
+    HetznerService, NamecheapService, OvhService, PorkbunService, PowerDnsService, Rfc2136Service,
+    Route53Service, Service, YDNSService,
+};
+use state::State;
 use std::{
-    error::Error,
-    fs,
-    io::{self, Write},
-    path::Path,
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
 };
-use tldextract::{TldExtractor, TldOption};
-use toml;
 
-trait Service {
-    fn update(self, ip: &str) -> Result<(), Box<dyn Error>>;
+#[derive(Deserialize)]
+struct DaemonConfig {
+    /// Seconds between two checks of the public address.
+    #[serde(default = "default_interval")]
+    interval: u64,
+    /// Upper bound in seconds of the delay between retries after failures.
+    #[serde(default = "default_max_backoff")]
+    max_backoff: u64,
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn default_max_backoff() -> u64 {
+    3600
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            interval: default_interval(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+fn default_state_file() -> PathBuf {
+    PathBuf::from("dnsupdate-state.toml")
 }
 
 #[derive(Deserialize)]
-struct CloudflareService {
-    api_key: String,
-    account_email: String,
-    domains: Vec<String>,
+struct ServiceConfig {
+    /// Where the addresses and IDs pushed on previous runs are cached.
+    #[serde(default = "default_state_file")]
+    state_file: PathBuf,
+    #[serde(default)]
+    ip: IpConfig,
+    #[serde(default)]
+    daemon: DaemonConfig,
+    #[serde(default, deserialize_with = "one_or_many")]
+    cloudflare: Vec<CloudflareService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    ydns: Vec<YDNSService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    dyndns2: Vec<Dyndns2Service>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    duckdns: Vec<DuckDNSService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    rfc2136: Vec<Rfc2136Service>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    route53: Vec<Route53Service>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    digitalocean: Vec<DigitalOceanService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    hetzner: Vec<HetznerService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    gandi: Vec<GandiService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    ovh: Vec<OvhService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    porkbun: Vec<PorkbunService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    desec: Vec<DesecService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    namecheap: Vec<NamecheapService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    afraid: Vec<AfraidService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    he: Vec<HeService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    powerdns: Vec<PowerDnsService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    gcloud_dns: Vec<GcloudDnsService>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    azure_dns: Vec<AzureDnsService>,
 }
 
-impl Service for CloudflareService {
-    fn update(self, ip: &str) -> Result<(), Box<dyn Error>> {
-        for subdomain in self.domains {
-            let tld = TldExtractor::new(TldOption::default())
-                .extract(&subdomain)
-                .unwrap();
-
-            print!("[Cloudflare] Update {subdomain}: ");
-            io::stdout().flush()?;
-
-            let mut headers = reqwest::header::HeaderMap::default();
-            headers.insert("X-Auth-Email", self.account_email.clone().parse().unwrap());
-            headers.insert("X-Auth-Key", self.api_key.clone().parse().unwrap());
-            headers.insert("Content-Type", "application/json".parse().unwrap());
-
-            let client = reqwest::blocking::ClientBuilder::default()
-                .default_headers(headers)
-                .build()?;
-
-            // Get zoneid for current zone
-            let resp = json::parse(&client.get(format!(
-                    "https://api.cloudflare.com/client/v4/zones?name={}.{}&status=active&per_page=1&page=1",
-                    tld.domain.as_ref().unwrap(),
-                    tld.suffix.as_ref().unwrap()
-                )).send()?.text()?)?;
-
-            if resp["result"].len() < 1 {
-                continue;
-            }
-            let zone_id = resp["result"][0]["id"].as_str().unwrap();
-
-            // Get DNS record ID
-            let sub = match tld.subdomain.as_ref() {
-                Some(a) => String::from(".") + a,
-                None => "".to_string(),
-            };
-
-            let resp = json::parse(
-                &client
-                    .get(format!(
-                        "https://api.cloudflare.com/client/v4/zones/{}/dns_records?name={}{}.{}",
-                        zone_id,
-                        sub,
-                        tld.domain.as_ref().unwrap(),
-                        tld.suffix.as_ref().unwrap()
-                    ))
-                    .send()?
-                    .text()?,
-            )?;
-
-            if resp["result"].len() < 1 {
-                continue;
-            }
+impl ServiceConfig {
+    fn services(&self) -> Vec<&dyn Service> {
+        let mut services: Vec<&dyn Service> = vec![];
+        services.extend(self.cloudflare.iter().map(|s| s as &dyn Service));
+        services.extend(self.ydns.iter().map(|s| s as &dyn Service));
+        services.extend(self.dyndns2.iter().map(|s| s as &dyn Service));
+        services.extend(self.duckdns.iter().map(|s| s as &dyn Service));
+        services.extend(self.rfc2136.iter().map(|s| s as &dyn Service));
+        services.extend(self.route53.iter().map(|s| s as &dyn Service));
+        services.extend(self.digitalocean.iter().map(|s| s as &dyn Service));
+        services.extend(self.hetzner.iter().map(|s| s as &dyn Service));
+        services.extend(self.gandi.iter().map(|s| s as &dyn Service));
+        services.extend(self.ovh.iter().map(|s| s as &dyn Service));
+        services.extend(self.porkbun.iter().map(|s| s as &dyn Service));
+        services.extend(self.desec.iter().map(|s| s as &dyn Service));
+        services.extend(self.namecheap.iter().map(|s| s as &dyn Service));
+        services.extend(self.afraid.iter().map(|s| s as &dyn Service));
+        services.extend(self.he.iter().map(|s| s as &dyn Service));
+        services.extend(self.powerdns.iter().map(|s| s as &dyn Service));
+        services.extend(self.gcloud_dns.iter().map(|s| s as &dyn Service));
+        services.extend(self.azure_dns.iter().map(|s| s as &dyn Service));
+        services
+    }
 
-            if !(resp["result"][0]["type"] == "A") && !(resp["result"][0]["type"] == "AAAA") {
-                continue;
+    /// Checks that no two services share a label, as the label also keys
+    /// their cached IDs in the state file.
+    fn check_labels(&self) -> Result<(), String> {
+        let mut labels = HashSet::new();
+        for service in self.services() {
+            let label = service.label();
+            if !labels.insert(label.clone()) {
+                return Err(format!(
+                    "several sections are labeled {label}, give each a distinct name"
+                ));
             }
-
-            let proxied = resp["result"][0]["proxied"].as_bool().unwrap();
-
-            let record_id = resp["result"][0]["id"].as_str().unwrap();
-
-            // Update the record
-            let resp = json::parse(
-                &client
-                    .put(format!(
-                        "https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records/{}",
-                        record_id
-                    ))
-                    .body(format!(
-                        r#"{{"id":"{}","content":"{}","type":"A","name":"{}{}.{}","proxied":{}}}"#,
-                        record_id,
-                        ip,
-                        sub,
-                        tld.domain.as_ref().unwrap(),
-                        tld.suffix.as_ref().unwrap(),
-                        proxied
-                    ))
-                    .send()?
-                    .text()?,
-            )?;
-
-            println!(
-                "{}",
-                match resp["success"].as_bool().unwrap() {
-                    true => "Success",
-                    false => "Fail",
-                }
-            );
         }
-
         Ok(())
     }
 }
 
-#[derive(Deserialize)]
-struct YDNSService {
-    user: String,
-    password: String,
-    domains: Vec<String>,
-}
+/// Pushes the addresses to every configured service and saves the state.
+/// Returns whether all of them succeeded.
+fn update_all(config: &ServiceConfig, ips: &Addresses, state: &mut State) -> bool {
+    let mut ok = true;
 
-impl Service for YDNSService {
-    fn update(self, ip: &str) -> Result<(), Box<dyn Error>> {
-        for subdomain in self.domains {
-            print!("[YDNS] Update {subdomain}: ");
-            io::stdout().flush()?;
-
-            let client = Client::new();
-            let resp = client
-                .get(format!(
-                    "https://ydns.io/api/v1/update/?host={}&ip={}",
-                    subdomain, ip
-                ))
-                .basic_auth(self.user.to_owned(), Some(self.password.to_owned()))
-                .send()
-                .unwrap()
-                .status();
-
-            println!(
-                "{}",
-                match resp {
-                    StatusCode::OK => "Success",
-                    _ => "Fail",
-                }
-            );
+    for service in config.services() {
+        let state = state.service(&service.label());
+
+        if service.unchanged(ips, state) {
+            println!("{} Nothing to update", service.label());
+            continue;
         }
 
-        Ok(())
+        if let Err(e) = service.verify() {
+            println!("{} Skipped: {e}", service.label());
+            ok = false;
+            continue;
+        }
+
+        if let Err(e) = service.update(ips, state) {
+            println!("{} Error: {e}", service.label());
+            ok = false;
+        }
+    }
+
+    if let Err(e) = state.save(&config.state_file) {
+        println!(
+            "Cannot save state file {}: {e}",
+            config.state_file.display()
+        );
     }
+
+    ok
 }
 
-#[derive(Deserialize)]
-struct ServiceConfig {
-    cloudflare: CloudflareService,
-    ydns: YDNSService,
+/// Checks the public address every `interval` seconds and updates the
+/// services when it changed. Failed runs are retried with an exponential
+/// backoff capped at `max_backoff` seconds.
+fn run_daemon(config: &ServiceConfig, state: &mut State) -> ! {
+    let mut last: Option<Addresses> = None;
+    let mut failures: u32 = 0;
+
+    loop {
+        let ok = match config.ip.detect() {
+            None => {
+                println!("Cannot detect public IP address");
+                false
+            }
+            Some(ips) if last == Some(ips) => true,
+            Some(ips) => {
+                println!("Public address: {ips}");
+                let ok = update_all(config, &ips, state);
+                if ok {
+                    last = Some(ips);
+                }
+                ok
+            }
+        };
+
+        let delay = match ok {
+            true => {
+                failures = 0;
+                config.daemon.interval
+            }
+            false => {
+                failures += 1;
+                let backoff = 30u64
+                    .saturating_mul(1 << (failures - 1).min(16))
+                    .min(config.daemon.max_backoff);
+                println!("Retrying in {backoff} seconds");
+                backoff
+            }
+        };
+
+        thread::sleep(Duration::from_secs(delay));
+    }
 }
 
 fn main() {
-    let my_ip = Client::default()
-        .get("https://myexternalip.com/raw")
-        .send()
-        .unwrap()
-        .text()
-        .unwrap();
+    let mut daemon = false;
+    let mut force = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--daemon" => daemon = true,
+            "--force" => force = true,
+            _ => panic!("Unknown argument: {arg}"),
+        }
+    }
 
     let config_paths = vec![
         Path::new(".config.toml"),
@@ -187,8 +249,38 @@ fn main() {
     println!("Config file path: {}", config_path.display());
 
     let config: ServiceConfig =
-        toml::from_str(&fs::read_to_string(config_path.to_str().unwrap()).unwrap()).unwrap();
+        match toml::from_str(&fs::read_to_string(config_path.to_str().unwrap()).unwrap()) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Invalid config: {e}");
+                process::exit(1);
+            }
+        };
+    if let Err(e) = config.check_labels() {
+        println!("Invalid config: {e}");
+        process::exit(1);
+    }
 
-    config.cloudflare.update(&my_ip).unwrap();
-    config.ydns.update(&my_ip).unwrap();
+    // With --force the cache is ignored, but still rewritten after the run
+    let mut state = match force {
+        true => State::default(),
+        false => State::load(&config.state_file),
+    };
+
+    if daemon {
+        run_daemon(&config, &mut state);
+    }
+
+    let my_ips = match config.ip.detect() {
+        Some(ips) => ips,
+        None => {
+            println!("Cannot detect public IP address!");
+            process::exit(1);
+        }
+    };
+    println!("Public address: {my_ips}");
+
+    if !update_all(&config, &my_ips, &mut state) {
+        process::exit(1);
+    }
 }
@@ -0,0 +1,248 @@
+use super::{failures, label, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    io::{self, Write},
+    net::IpAddr,
+};
+
+/// FreeDNS (afraid.org). Hosts are updated with their v2 update token when
+/// one is configured, else with the update URL listed by the v1 API for the
+/// account.
+#[derive(Deserialize)]
+pub struct AfraidService {
+    name: Option<String>,
+    /// Account credentials, to look up the hosts without a token.
+    user: Option<String>,
+    password: Option<String>,
+    /// v2 randomized update tokens of A records, by hostname.
+    #[serde(default)]
+    tokens: BTreeMap<String, String>,
+    /// v2 randomized update tokens of AAAA records, by hostname.
+    #[serde(default)]
+    ipv6_tokens: BTreeMap<String, String>,
+    domains: Vec<String>,
+}
+
+/// A dynamic host of the account, as listed by the v1 API.
+struct Host {
+    name: String,
+    ip: Option<IpAddr>,
+    url: String,
+}
+
+/// Outcome of an update, read from the response body.
+enum Status {
+    Success,
+    Unchanged,
+    Permanent(String),
+    Transient(String),
+}
+
+fn parse(body: &str) -> Status {
+    let body = body.trim();
+    if body.starts_with("Updated") {
+        Status::Success
+    } else if body.contains("has not changed") || body.contains("No IP change detected") {
+        Status::Unchanged
+    } else if body.contains("Could not authenticate")
+        || body.contains("Unable to locate this record")
+        || body.contains("Invalid update URL")
+    {
+        Status::Permanent(body.trim_start_matches("ERROR: ").to_string())
+    } else {
+        Status::Transient(format!(
+            "unexpected response {:?}",
+            body.chars().take(80).collect::<String>()
+        ))
+    }
+}
+
+impl AfraidService {
+    /// Lists the dynamic hosts of the account through the v1 API, keyed by
+    /// the SHA1 of `user|password`.
+    fn hosts(&self, client: &Client) -> Result<Vec<Host>, Box<dyn Error>> {
+        let (user, password) = match (&self.user, &self.password) {
+            (Some(user), Some(password)) => (user, password),
+            _ => return Ok(vec![]),
+        };
+        let sha = hex::encode(Sha1::digest(format!("{user}|{password}")));
+
+        let resp = client
+            .get("https://freedns.afraid.org/api/")
+            .query(&[("action", "getdyndns"), ("v", "2"), ("sha", &sha)])
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        if resp.starts_with("ERROR") {
+            return Err(resp.trim().trim_start_matches("ERROR: ").into());
+        }
+
+        // hostname|current address|update URL
+        Ok(resp
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('|');
+                Some(Host {
+                    name: fields.next()?.to_string(),
+                    ip: fields.next()?.parse().ok(),
+                    url: fields.next()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// Returns the update URL of the record of `domain` holding `ip`, with
+    /// the address parameter to add to it.
+    fn update_url(
+        &self,
+        domain: &str,
+        ip: &IpAddr,
+        hosts: &[Host],
+    ) -> Option<(String, &'static str)> {
+        let token = match ip {
+            IpAddr::V4(_) => self
+                .tokens
+                .get(domain)
+                .map(|token| format!("https://sync.afraid.org/u/{token}/")),
+            IpAddr::V6(_) => self
+                .ipv6_tokens
+                .get(domain)
+                .map(|token| format!("https://v6.sync.afraid.org/u/{token}/")),
+        };
+        if let Some(url) = token {
+            return Some((url, "ip"));
+        }
+
+        hosts
+            .iter()
+            .find(|host| {
+                host.name.eq_ignore_ascii_case(domain)
+                    && host
+                        .ip
+                        .is_some_and(|host_ip| host_ip.is_ipv4() == ip.is_ipv4())
+            })
+            .map(|host| (host.url.clone(), "address"))
+    }
+}
+
+impl Service for AfraidService {
+    fn label(&self) -> String {
+        label("FreeDNS", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        match (&self.user, &self.password) {
+            (Some(_), None) | (None, Some(_)) => {
+                Err("user and password must be set together".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let hosts = self.hosts(&client)?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let mut updated = 0;
+            let mut ok = true;
+            for record_type in ["A", "AAAA"] {
+                let ip = match ips.for_record(record_type) {
+                    Some(ip) => ip,
+                    None => continue,
+                };
+                let (url, param) = match self.update_url(domain, &ip, &hosts) {
+                    Some(url) => url,
+                    None => continue,
+                };
+
+                let resp = client
+                    .get(&url)
+                    .query(&[(param, ip.to_string())])
+                    .send()?
+                    .text()?;
+
+                print!("{record_type} ");
+                match parse(&resp) {
+                    Status::Success => print!("Success "),
+                    Status::Unchanged => print!("Unchanged "),
+                    Status::Permanent(e) => {
+                        print!("Fail: {e} ");
+                        cache.permanent_error = Some(e);
+                        failed += 1;
+                        ok = false;
+                    }
+                    Status::Transient(e) => {
+                        print!("Fail: {e} ");
+                        failed += 1;
+                        ok = false;
+                    }
+                }
+                updated += 1;
+            }
+
+            match updated {
+                0 => {
+                    println!("Fail: no update token or listed host for the detected addresses");
+                    failed += 1;
+                }
+                _ => println!(),
+            }
+
+            if ok && updated > 0 {
+                cache.succeeded(ips);
+            }
+        }
+
+        failures(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_responses() {
+        assert!(matches!(
+            parse("Updated 1 host(s) host.example.com to 192.0.2.1 in 0.150 seconds"),
+            Status::Success
+        ));
+        assert!(matches!(
+            parse("ERROR: Address 192.0.2.1 has not changed."),
+            Status::Unchanged
+        ));
+        assert!(matches!(
+            parse("No IP change detected for host.example.com with IP 192.0.2.1, skipping update"),
+            Status::Unchanged
+        ));
+        assert!(matches!(
+            parse("ERROR: Unable to locate this record"),
+            Status::Permanent(e) if e == "Unable to locate this record"
+        ));
+        assert!(matches!(
+            parse("<html>Service Unavailable</html>"),
+            Status::Transient(_)
+        ));
+    }
+}
@@ -0,0 +1,233 @@
+use super::{default_ttl, failures, label, send, split_domain, Service};
+use crate::{ip::Addresses, jwt, state::ServiceState};
+use json::JsonValue;
+use reqwest::{blocking::Client, StatusCode};
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const API_VERSION: &str = "2018-05-01";
+
+/// Azure DNS zones, through Azure Resource Manager, authenticated as an
+/// Entra ID application with a client secret or certificate.
+#[derive(Deserialize)]
+pub struct AzureDnsService {
+    name: Option<String>,
+    tenant_id: String,
+    client_id: String,
+    client_secret: Option<String>,
+    /// PEM file holding the certificate of the application and its private
+    /// key, used when no client_secret is set.
+    certificate_file: Option<PathBuf>,
+    subscription_id: String,
+    resource_group: String,
+    /// Zone of the domains, guessed from the public suffix list when unset.
+    zone: Option<String>,
+    #[serde(default = "default_ttl::<300>")]
+    ttl: u32,
+    /// Entra ID and ARM base URLs, overridable to test against local
+    /// stand-ins.
+    #[serde(default = "default_authority_url")]
+    authority_url: String,
+    #[serde(default = "default_management_url")]
+    management_url: String,
+    domains: Vec<String>,
+}
+
+fn default_authority_url() -> String {
+    "https://login.microsoftonline.com".to_string()
+}
+
+fn default_management_url() -> String {
+    "https://management.azure.com".to_string()
+}
+
+impl AzureDnsService {
+    /// Builds the client assertion proving possession of the certificate.
+    fn client_assertion(&self, token_url: &str) -> Result<String, Box<dyn Error>> {
+        let path = self
+            .certificate_file
+            .as_ref()
+            .ok_or("client_secret or certificate_file must be set")?;
+        let pem =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        jwt::sign(
+            json::object! { x5t: jwt::thumbprint(&pem)? },
+            &json::object! {
+                aud: token_url,
+                iss: self.client_id.as_str(),
+                sub: self.client_id.as_str(),
+                jti: format!("{:x}", now.as_nanos()),
+                nbf: now.as_secs(),
+                exp: now.as_secs() + 600,
+            },
+            &jwt::private_key(&pem)?,
+        )
+    }
+
+    /// Gets an ARM access token through the client credentials flow.
+    fn access_token(&self, client: &Client) -> Result<String, Box<dyn Error>> {
+        let token_url = format!(
+            "{}/{}/oauth2/v2.0/token",
+            self.authority_url.trim_end_matches('/'),
+            self.tenant_id
+        );
+        // The scope names ARM itself, even with a stand-in management_url
+        let scope = format!("{}/.default", default_management_url());
+
+        let mut form = vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("client_id", self.client_id.clone()),
+            ("scope", scope),
+        ];
+        match &self.client_secret {
+            Some(secret) => form.push(("client_secret", secret.clone())),
+            None => {
+                form.push((
+                    "client_assertion_type",
+                    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer".to_string(),
+                ));
+                form.push(("client_assertion", self.client_assertion(&token_url)?));
+            }
+        }
+
+        let resp = client.post(&token_url).form(&form).send()?;
+        let status = resp.status();
+        let body = json::parse(&resp.text()?).unwrap_or(JsonValue::Null);
+
+        match body["access_token"].as_str() {
+            Some(token) if status.is_success() => Ok(token.to_string()),
+            _ => match body["error_description"].as_str() {
+                Some(e) => Err(format!("token request failed: {e}").into()),
+                None => Err(format!("token request failed: HTTP status {status}").into()),
+            },
+        }
+    }
+}
+
+impl Service for AzureDnsService {
+    fn label(&self) -> String {
+        label("AzureDNS", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        if self.client_secret.is_some() {
+            return Ok(());
+        }
+
+        let path = self
+            .certificate_file
+            .as_ref()
+            .ok_or("client_secret or certificate_file must be set")?;
+        let pem =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        jwt::thumbprint(&pem)?;
+        jwt::private_key(&pem)?;
+        Ok(())
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let token = self.access_token(&client)?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let (zone, relative) = match &self.zone {
+                Some(zone) => {
+                    let zone = zone.trim_end_matches('.');
+                    let relative = match domain.strip_suffix(zone) {
+                        Some("") => "",
+                        Some(sub) if sub.ends_with('.') => sub.trim_end_matches('.'),
+                        _ => {
+                            let e = format!("domain is not in zone {zone}");
+                            println!("Fail: {e}");
+                            cache.permanent_error = Some(e);
+                            failed += 1;
+                            continue;
+                        }
+                    };
+                    (zone.to_string(), relative.to_string())
+                }
+                None => {
+                    let (sub, zone) = split_domain(domain)?;
+                    (zone, sub)
+                }
+            };
+            let relative = match relative.is_empty() {
+                true => "@".to_string(),
+                false => relative,
+            };
+
+            let mut ok = true;
+            for record_type in ["A", "AAAA"] {
+                let ip = match ips.for_record(record_type) {
+                    Some(ip) => ip,
+                    None => continue,
+                };
+
+                let mut properties = json::object! { TTL: self.ttl };
+                match record_type {
+                    "A" => properties["ARecords"] = json::array![{ ipv4Address: ip.to_string() }],
+                    _ => properties["AAAARecords"] = json::array![{ ipv6Address: ip.to_string() }],
+                }
+
+                // PUT replaces the whole record set, creating it if needed
+                let resp = send(
+                    client
+                        .put(format!(
+                            "{}/subscriptions/{}/resourceGroups/{}/providers/Microsoft.Network/dnsZones/{zone}/{record_type}/{relative}",
+                            self.management_url.trim_end_matches('/'),
+                            self.subscription_id,
+                            self.resource_group,
+                        ))
+                        .query(&[("api-version", API_VERSION)])
+                        .bearer_auth(&token)
+                        .header("Content-Type", "application/json")
+                        .body(json::object! { properties: properties }.dump()),
+                    &["error", "message"],
+                    &[StatusCode::NOT_FOUND],
+                )?;
+
+                print!("{record_type} ");
+                match resp {
+                    Ok(_) => print!("Success "),
+                    Err((e, permanent)) => {
+                        print!("Fail: {e} ");
+                        if permanent {
+                            cache.permanent_error = Some(e);
+                        }
+                        failed += 1;
+                        ok = false;
+                    }
+                }
+            }
+            println!();
+
+            if ok {
+                cache.succeeded(ips);
+            }
+        }
+
+        failures(failed)
+    }
+}
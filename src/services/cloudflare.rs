@@ -0,0 +1,287 @@
+use super::{
+    default_ttl, failures, label, split_domain, update_records, ApiResult, Domain, Service,
+};
+use crate::{ip::Addresses, state::ServiceState};
+use json::JsonValue;
+use reqwest::{blocking::Client, header::HeaderMap};
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+#[derive(Deserialize)]
+pub struct CloudflareService {
+    name: Option<String>,
+    /// Scoped API token, sent as `Authorization: Bearer`. Takes precedence over
+    /// the legacy global key unless empty.
+    api_token: Option<String>,
+    api_key: Option<String>,
+    account_email: Option<String>,
+    #[serde(default)]
+    create_missing: bool,
+    /// TTL of created records, 1 meaning automatic.
+    #[serde(default = "default_ttl::<1>")]
+    ttl: u32,
+    /// Whether created records are proxied through Cloudflare.
+    #[serde(default)]
+    proxied: bool,
+    domains: Vec<Domain>,
+}
+
+/// Joins the `errors` array of a Cloudflare API response into one message.
+fn cloudflare_errors(resp: &JsonValue) -> String {
+    match resp["errors"].is_empty() {
+        true => "unexpected response".to_string(),
+        false => resp["errors"]
+            .members()
+            .map(|e| format!("{} (code {})", e["message"], e["code"]))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Reads the `result` of a Cloudflare API response, whose `success` flag
+/// tells whether the call worked, whatever the HTTP status.
+fn cloudflare_result(resp: &JsonValue) -> ApiResult<&JsonValue> {
+    match resp["success"].as_bool() {
+        Some(true) => Ok(&resp["result"]),
+        _ => Err((cloudflare_errors(resp), false)),
+    }
+}
+
+/// Reads the ID of an updated or created record, hinting at the missing
+/// permission when the token is not allowed to edit records.
+fn record_id_of(resp: &JsonValue) -> ApiResult<Option<String>> {
+    match cloudflare_result(resp) {
+        Ok(record) => Ok(Some(record["id"].to_string())),
+        Err(_) if resp["errors"][0]["code"] == 10000 || resp["errors"][0]["code"] == 9109 => Err((
+            format!(
+                "is the API token missing the DNS:Edit permission? {}",
+                cloudflare_errors(resp)
+            ),
+            false,
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+impl CloudflareService {
+    /// The API token, unless left empty.
+    fn api_token(&self) -> Option<&str> {
+        self.api_token.as_deref().filter(|token| !token.is_empty())
+    }
+
+    fn client(&self) -> Result<Client, Box<dyn Error>> {
+        let mut headers = HeaderMap::default();
+        match (self.api_token(), &self.api_key, &self.account_email) {
+            (Some(token), _, _) => {
+                headers.insert("Authorization", format!("Bearer {token}").parse()?);
+            }
+            (None, Some(key), Some(email)) => {
+                headers.insert("X-Auth-Email", email.parse()?);
+                headers.insert("X-Auth-Key", key.parse()?);
+            }
+            _ => return Err("set either api_token, or api_key and account_email".into()),
+        }
+        headers.insert("Content-Type", "application/json".parse()?);
+
+        Ok(reqwest::blocking::ClientBuilder::default()
+            .default_headers(headers)
+            .build()?)
+    }
+
+    /// Checks that the configured API token is active and can read the zones
+    /// and DNS records of every configured domain. Whether it may also edit
+    /// the records only shows on update. Does nothing when the legacy global
+    /// key is used.
+    fn verify_token(&self) -> Result<(), Box<dyn Error>> {
+        if self.api_token().is_none() {
+            return Ok(());
+        }
+
+        let client = self.client()?;
+
+        let resp = json::parse(
+            &client
+                .get("https://api.cloudflare.com/client/v4/user/tokens/verify")
+                .send()?
+                .text()?,
+        )?;
+        if resp["success"].as_bool() != Some(true) {
+            return Err(format!("API token is invalid: {}", cloudflare_errors(&resp)).into());
+        }
+        if resp["result"]["status"] != "active" {
+            return Err(format!("API token is {}", resp["result"]["status"]).into());
+        }
+
+        for domain in &self.domains {
+            let (_, zone) = split_domain(&domain.name)?;
+
+            let resp = json::parse(
+                &client
+                    .get(format!(
+                        "https://api.cloudflare.com/client/v4/zones?name={zone}&status=active&per_page=1&page=1"
+                    ))
+                    .send()?
+                    .text()?,
+            )?;
+            if resp["success"].as_bool() != Some(true) || resp["result"].is_empty() {
+                return Err(format!(
+                    "API token cannot read zone {zone}, is it missing the Zone:Read permission? {}",
+                    cloudflare_errors(&resp)
+                )
+                .into());
+            }
+
+            let resp = json::parse(
+                &client
+                    .get(format!(
+                        "https://api.cloudflare.com/client/v4/zones/{}/dns_records?per_page=1",
+                        resp["result"][0]["id"]
+                    ))
+                    .send()?
+                    .text()?,
+            )?;
+            if resp["success"].as_bool() != Some(true) {
+                return Err(format!(
+                    "API token cannot read DNS records of zone {zone}, is it missing the DNS:Read permission? {}",
+                    cloudflare_errors(&resp)
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Service for CloudflareService {
+    fn label(&self) -> String {
+        label("Cloudflare", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        self.verify_token()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            let (_, zone) = split_domain(&domain.name)?;
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            // Get zoneid for current zone
+            let zone_id = match &cache.zone_id {
+                Some(zone_id) => zone_id.clone(),
+                None => {
+                    let resp = json::parse(&client.get(format!(
+                            "https://api.cloudflare.com/client/v4/zones?name={zone}&status=active&per_page=1&page=1"
+                        )).send()?.text()?)?;
+
+                    match cloudflare_result(&resp) {
+                        Ok(zones) if !zones.is_empty() => zones[0]["id"].to_string(),
+                        Ok(_) => {
+                            println!("Fail: zone not found");
+                            failed += 1;
+                            continue;
+                        }
+                        Err((e, _)) => {
+                            println!("Fail: {e}");
+                            failed += 1;
+                            continue;
+                        }
+                    }
+                }
+            };
+            cache.zone_id = Some(zone_id.clone());
+
+            let create_missing = domain.create_missing.unwrap_or(self.create_missing);
+            let records_url =
+                format!("https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records");
+
+            // DNS records of the domain, only fetched when an ID is not cached
+            let mut records: Option<JsonValue> = None;
+
+            let domain_failed = update_records(
+                cache,
+                ips,
+                create_missing,
+                |record_type, cached| {
+                    if let Some(record_id) = cached {
+                        return Ok(Ok(Some(record_id.to_string())));
+                    }
+                    let records = match records {
+                        Some(ref records) => records,
+                        None => &*records.insert(json::parse(
+                            &client
+                                .get(&records_url)
+                                .query(&[("name", &domain.name)])
+                                .send()?
+                                .text()?,
+                        )?),
+                    };
+                    Ok(cloudflare_result(records).map(|result| {
+                        result
+                            .members()
+                            .find(|record| record["type"] == record_type)
+                            .map(|record| record["id"].to_string())
+                    }))
+                },
+                // Update the record content, keeping its type and proxy status
+                |_, record_id, ip| {
+                    let resp = json::parse(
+                        &client
+                            .patch(format!("{records_url}/{record_id}"))
+                            .body(format!(r#"{{"content":"{}"}}"#, ip))
+                            .send()?
+                            .text()?,
+                    )?;
+                    Ok(record_id_of(&resp))
+                },
+                // Create the missing record
+                |record_type, ip| {
+                    let resp = json::parse(
+                        &client
+                            .post(&records_url)
+                            .body(format!(
+                                r#"{{"content":"{}","type":"{}","name":"{}","ttl":{},"proxied":{}}}"#,
+                                ip,
+                                record_type,
+                                domain.name,
+                                domain.ttl.unwrap_or(self.ttl),
+                                domain.proxied.unwrap_or(self.proxied)
+                            ))
+                            .send()?
+                            .text()?,
+                    )?;
+                    Ok(record_id_of(&resp))
+                },
+            )?;
+            println!();
+
+            failed += domain_failed;
+            match domain_failed {
+                0 => cache.succeeded(ips),
+                // The cached IDs may be stale, e.g. a record was deleted
+                _ => cache.invalidate(),
+            }
+        }
+
+        failures(failed)
+    }
+}
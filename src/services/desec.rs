@@ -0,0 +1,240 @@
+use super::{
+    default_ttl,
+    dyndns2::{self, Status},
+    failures, label, ApiResult, Domain, Service,
+};
+use crate::{ip::Addresses, state::ServiceState};
+use json::JsonValue;
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    StatusCode,
+};
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+    thread,
+    time::Duration,
+};
+
+/// Requests retried after a 429 before giving up.
+const MAX_RETRIES: u32 = 3;
+
+#[derive(Deserialize)]
+pub struct DesecService {
+    name: Option<String>,
+    token: String,
+    /// Update through the dyndns2-compatible endpoint instead of the REST
+    /// API, which ignores ttl.
+    #[serde(default)]
+    dyndns: bool,
+    /// TTL of the rrsets, at least 3600 unless the account allows less.
+    #[serde(default = "default_ttl::<3600>")]
+    ttl: u32,
+    domains: Vec<Domain>,
+}
+
+/// Sends a request, waiting and retrying as long as the server asks to
+/// through `Retry-After`, since deSEC throttles aggressively.
+fn send_with_retry(request: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+    let mut retries = 0;
+    loop {
+        let resp = request
+            .try_clone()
+            .ok_or("request cannot be retried")?
+            .send()?;
+        if resp.status() != StatusCode::TOO_MANY_REQUESTS || retries == MAX_RETRIES {
+            return Ok(resp);
+        }
+
+        let wait = resp
+            .headers()
+            .get("Retry-After")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1);
+        print!("(throttled, retrying in {wait}s) ");
+        io::stdout().flush()?;
+        thread::sleep(Duration::from_secs(wait));
+        retries += 1;
+    }
+}
+
+/// Reads the message of a failed response and whether it is permanent.
+fn error(resp: Response) -> Result<(String, bool), Box<dyn Error>> {
+    let status = resp.status();
+    let body = json::parse(&resp.text()?).unwrap_or(JsonValue::Null);
+    let message = match &body["detail"] {
+        JsonValue::Null if body.is_null() => format!("HTTP status {status}"),
+        JsonValue::Null => body.dump(),
+        detail => detail.to_string(),
+    };
+    let permanent = matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN);
+    Ok((message, permanent))
+}
+
+impl DesecService {
+    /// Finds the domain of the account that `name` belongs to. Names may be
+    /// below a public suffix such as dedyn.io, so the public suffix list
+    /// cannot tell.
+    fn zone(&self, client: &Client, name: &str) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let resp = send_with_retry(
+            client
+                .get("https://desec.io/api/v1/domains/")
+                .query(&[("owns_qname", name)])
+                .header("Authorization", format!("Token {}", self.token)),
+        )?;
+        if !resp.status().is_success() {
+            return Ok(Err(error(resp)?));
+        }
+
+        let domains = json::parse(&resp.text()?)?;
+        Ok(match domains[0]["name"].as_str() {
+            Some(zone) => Ok(zone.to_string()),
+            None => Err((
+                "no domain of the account contains this name".to_string(),
+                true,
+            )),
+        })
+    }
+
+    /// Replaces the A/AAAA rrsets of a domain of `zone` in one bulk request,
+    /// which also creates them. Returns the error message and whether it is
+    /// permanent.
+    fn update_rrsets(
+        &self,
+        client: &Client,
+        domain: &Domain,
+        zone: &str,
+        ips: &Addresses,
+    ) -> Result<ApiResult<()>, Box<dyn Error>> {
+        let sub = match domain.name.strip_suffix(zone) {
+            Some(sub) => sub.trim_end_matches('.'),
+            None => "",
+        };
+
+        let mut rrsets = JsonValue::new_array();
+        for record_type in ["A", "AAAA"] {
+            if let Some(ip) = ips.for_record(record_type) {
+                rrsets.push(json::object! {
+                    subname: sub,
+                    type: record_type,
+                    ttl: domain.ttl.unwrap_or(self.ttl),
+                    records: [ip.to_string()],
+                })?;
+            }
+        }
+
+        let resp = send_with_retry(
+            client
+                .patch(format!("https://desec.io/api/v1/domains/{zone}/rrsets/"))
+                .header("Authorization", format!("Token {}", self.token))
+                .header("Content-Type", "application/json")
+                .body(rrsets.dump()),
+        )?;
+        Ok(match resp.status().is_success() {
+            true => Ok(()),
+            false => Err(error(resp)?),
+        })
+    }
+
+    /// Updates a domain through update.dedyn.io. Both families are sent in
+    /// one request, an undetected one as `preserve`, since a missing one is
+    /// otherwise deleted.
+    fn update_dyndns(
+        &self,
+        client: &Client,
+        domain: &Domain,
+        ips: &Addresses,
+    ) -> Result<Status, Box<dyn Error>> {
+        let param = |ip: Option<String>| ip.unwrap_or_else(|| "preserve".to_string());
+
+        let resp = send_with_retry(
+            client
+                .get("https://update.dedyn.io/")
+                .query(&[
+                    ("hostname", domain.name.clone()),
+                    ("myipv4", param(ips.ipv4.map(|ip| ip.to_string()))),
+                    ("myipv6", param(ips.ipv6.map(|ip| ip.to_string()))),
+                ])
+                .basic_auth(&domain.name, Some(&self.token)),
+        )?;
+
+        dyndns2::status(resp)
+    }
+}
+
+impl Service for DesecService {
+    fn label(&self) -> String {
+        label("deSEC", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::builder()
+            .user_agent(concat!("dnsupdate/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let status = match self.dyndns {
+                true => self.update_dyndns(&client, domain, ips)?,
+                false => {
+                    let zone = match &cache.zone_id {
+                        Some(zone) => Ok(zone.clone()),
+                        None => self.zone(&client, &domain.name)?,
+                    };
+                    let resp = match zone {
+                        Ok(zone) => {
+                            cache.zone_id = Some(zone.clone());
+                            self.update_rrsets(&client, domain, &zone, ips)?
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    match resp {
+                        Ok(()) => Status::Success,
+                        Err((e, permanent)) => {
+                            // The domain may have moved to another zone
+                            cache.invalidate();
+                            match permanent {
+                                true => Status::Permanent(e),
+                                false => Status::Transient(e),
+                            }
+                        }
+                    }
+                }
+            };
+
+            match status {
+                Status::Success => {
+                    println!("Success");
+                    cache.succeeded(ips);
+                }
+                Status::Permanent(e) => {
+                    println!("Fail: {e}");
+                    cache.permanent_error = Some(e);
+                    failed += 1;
+                }
+                Status::Transient(e) => {
+                    println!("Fail: {e}");
+                    failed += 1;
+                }
+            }
+        }
+
+        failures(failed)
+    }
+}
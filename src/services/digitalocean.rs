@@ -0,0 +1,117 @@
+use super::{default_ttl, failures, label, send, split_domain, update_records, Domain, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+#[derive(Deserialize)]
+pub struct DigitalOceanService {
+    name: Option<String>,
+    /// Personal access token with write access to domains.
+    token: String,
+    #[serde(default)]
+    create_missing: bool,
+    /// TTL of created records.
+    #[serde(default = "default_ttl::<1800>")]
+    ttl: u32,
+    domains: Vec<Domain>,
+}
+
+impl Service for DigitalOceanService {
+    fn label(&self) -> String {
+        label("DigitalOcean", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let (sub, zone) = split_domain(&domain.name)?;
+            let records_url = format!("https://api.digitalocean.com/v2/domains/{zone}/records");
+            let create_missing = domain.create_missing.unwrap_or(self.create_missing);
+
+            let domain_failed = update_records(
+                cache,
+                ips,
+                create_missing,
+                // Find the record by name and type, unless its ID is cached
+                |record_type, cached| {
+                    if let Some(record_id) = cached {
+                        return Ok(Ok(Some(record_id.to_string())));
+                    }
+                    let resp = send(
+                        client
+                            .get(&records_url)
+                            .bearer_auth(&self.token)
+                            .query(&[("type", record_type), ("name", &domain.name)]),
+                        &["message"],
+                        &[],
+                    )?;
+                    Ok(resp.map(|resp| {
+                        resp["domain_records"][0]["id"]
+                            .as_u64()
+                            .map(|id| id.to_string())
+                    }))
+                },
+                |_, record_id, ip| {
+                    let resp = send(
+                        client
+                            .patch(format!("{records_url}/{record_id}"))
+                            .bearer_auth(&self.token)
+                            .header("Content-Type", "application/json")
+                            .body(json::object! { data: ip.to_string() }.dump()),
+                        &["message"],
+                        &[],
+                    )?;
+                    Ok(resp.map(|resp| Some(resp["domain_record"]["id"].to_string())))
+                },
+                |record_type, ip| {
+                    let resp = send(
+                        client
+                            .post(&records_url)
+                            .bearer_auth(&self.token)
+                            .header("Content-Type", "application/json")
+                            .body(
+                                json::object! {
+                                    type: record_type,
+                                    name: if sub.is_empty() { "@" } else { sub.as_str() },
+                                    data: ip.to_string(),
+                                    ttl: domain.ttl.unwrap_or(self.ttl),
+                                }
+                                .dump(),
+                            ),
+                        &["message"],
+                        &[],
+                    )?;
+                    Ok(resp.map(|resp| Some(resp["domain_record"]["id"].to_string())))
+                },
+            )?;
+            println!();
+
+            failed += domain_failed;
+            match domain_failed {
+                0 => cache.succeeded(ips),
+                _ => cache.invalidate(),
+            }
+        }
+
+        failures(failed)
+    }
+}
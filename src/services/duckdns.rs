@@ -0,0 +1,95 @@
+use super::{failures, label, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+#[derive(Deserialize)]
+pub struct DuckDNSService {
+    name: Option<String>,
+    token: String,
+    /// Subdomains, with or without the `.duckdns.org` suffix.
+    domains: Vec<String>,
+    /// TXT record value, sent after every address update.
+    txt: Option<String>,
+}
+
+impl DuckDNSService {
+    /// Calls the update endpoint, which answers `OK` or `KO`.
+    fn call(&self, client: &Client, query: &[(&str, String)]) -> Result<bool, Box<dyn Error>> {
+        let resp = client
+            .get("https://www.duckdns.org/update")
+            .query(&[("token", &self.token)])
+            .query(query)
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        match resp.lines().next().unwrap_or("") {
+            "OK" => Ok(true),
+            "KO" => Ok(false),
+            other => Err(format!("unexpected response {other:?}").into()),
+        }
+    }
+}
+
+impl Service for DuckDNSService {
+    fn label(&self) -> String {
+        label("DuckDNS", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let subdomain = domain.trim_end_matches(".duckdns.org").to_string();
+
+            let mut query = vec![("domains", subdomain.clone())];
+            if let Some(ip) = ips.ipv4 {
+                query.push(("ip", ip.to_string()));
+            }
+            if let Some(ip) = ips.ipv6 {
+                query.push(("ipv6", ip.to_string()));
+            }
+
+            if !self.call(&client, &query)? {
+                let e = "bad token or domain".to_string();
+                println!("Fail: {e}");
+                cache.permanent_error = Some(e);
+                failed += 1;
+                continue;
+            }
+
+            if let Some(txt) = &self.txt {
+                let query = [("domains", subdomain), ("txt", txt.clone())];
+                if !self.call(&client, &query)? {
+                    println!("Fail: TXT record was not updated");
+                    failed += 1;
+                    continue;
+                }
+            }
+
+            println!("Success");
+            cache.succeeded(ips);
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,270 @@
+use super::{failures, label, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::{Client, Response};
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+/// Outcome of a dyndns2 update, read from the response body.
+pub enum Status {
+    /// `good` or `nochg`.
+    Success,
+    /// The request will keep failing until the configuration is fixed.
+    Permanent(String),
+    /// The request may succeed later.
+    Transient(String),
+}
+
+/// Parses the return code starting a dyndns2 response body.
+pub fn parse(body: &str) -> Status {
+    let body = body.trim();
+    match body.split_whitespace().next().unwrap_or("") {
+        "good" | "nochg" => Status::Success,
+        "badauth" => Status::Permanent("bad credentials".to_string()),
+        "nohost" => Status::Permanent("host does not exist in this account".to_string()),
+        "notfqdn" => Status::Permanent("host is not a fully qualified domain name".to_string()),
+        "!yours" => Status::Permanent("host is owned by another account".to_string()),
+        "numhost" => Status::Permanent("too many hosts in one request".to_string()),
+        "badagent" => Status::Permanent("client was blocked".to_string()),
+        "abuse" => Status::Permanent("host is blocked for abuse".to_string()),
+        "!donator" => Status::Permanent("feature needs a paid account".to_string()),
+        "911" | "dnserr" => Status::Transient(format!("server error ({body})")),
+        _ => Status::Transient(format!("unexpected response {body:?}")),
+    }
+}
+
+/// Reads the outcome of an update from its response.
+pub fn status(resp: Response) -> Result<Status, Box<dyn Error>> {
+    Ok(match resp.status().is_success() {
+        true => parse(&resp.text()?),
+        false => match resp.status().as_u16() {
+            401 | 403 => Status::Permanent("bad credentials".to_string()),
+            code => Status::Transient(format!("HTTP status {code}")),
+        },
+    })
+}
+
+/// How a dyndns2-style endpoint takes the IPv6 address.
+pub enum Ipv6Param<'a> {
+    /// Next to the IPv4 one in the address parameter, comma separated, as
+    /// in `myip=192.0.2.1,2001:db8::1`.
+    Joined,
+    /// In its own parameter of the same request.
+    Named(&'a str),
+    /// In a request of its own, for servers taking one address per request
+    /// that keep the A and AAAA records apart.
+    Separate,
+}
+
+/// A dyndns2-style update URL and its credentials.
+pub struct Endpoint<'a> {
+    pub url: &'a str,
+    /// Query parameter names of the host and the address, `hostname` and
+    /// `myip` in the protocol itself.
+    pub host_param: &'a str,
+    pub ip_param: &'a str,
+    pub ipv6_param: Ipv6Param<'a>,
+    pub user: &'a str,
+    pub password: &'a str,
+}
+
+impl Endpoint<'_> {
+    /// Address parameters of the requests sending `ips`, one request per
+    /// entry.
+    fn queries(&self, ips: &Addresses) -> Vec<Vec<(&str, String)>> {
+        let ipv4 = ips.ipv4.map(|ip| ip.to_string());
+        let ipv6 = ips.ipv6.map(|ip| ip.to_string());
+
+        match self.ipv6_param {
+            Ipv6Param::Joined => {
+                let ips: Vec<String> = [ipv4, ipv6].into_iter().flatten().collect();
+                vec![vec![(self.ip_param, ips.join(","))]]
+            }
+            Ipv6Param::Named(param) => vec![[(self.ip_param, ipv4), (param, ipv6)]
+                .into_iter()
+                .filter_map(|(param, ip)| Some((param, ip?)))
+                .collect()],
+            Ipv6Param::Separate => [ipv4, ipv6]
+                .into_iter()
+                .flatten()
+                .map(|ip| vec![(self.ip_param, ip)])
+                .collect(),
+        }
+    }
+}
+
+/// Sends the detected addresses to every domain, skipping domains whose
+/// cached state says there is nothing to do.
+pub fn update_domains(
+    label: &str,
+    endpoint: &Endpoint,
+    domains: &[String],
+    ips: &Addresses,
+    state: &mut ServiceState,
+) -> Result<(), Box<dyn Error>> {
+    let client = Client::builder()
+        .user_agent(concat!("dnsupdate/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let queries = endpoint.queries(ips);
+    let mut failed = 0;
+
+    for domain in domains {
+        let cache = state.entry(domain.clone()).or_default();
+        if let Some(reason) = cache.skip_reason(ips) {
+            println!("{label} Update {domain}: {reason}");
+            continue;
+        }
+
+        let mut ok = true;
+        for query in &queries {
+            let shown: Vec<&str> = query.iter().map(|(_, ip)| ip.as_str()).collect();
+            print!("{label} Update {domain} ({}): ", shown.join(", "));
+            io::stdout().flush()?;
+
+            let resp = client
+                .get(endpoint.url)
+                .query(&[(endpoint.host_param, domain)])
+                .query(query)
+                .basic_auth(endpoint.user, Some(endpoint.password))
+                .send()?;
+
+            match status(resp)? {
+                Status::Success => println!("Success"),
+                Status::Permanent(e) => {
+                    println!("Fail: {e}");
+                    cache.permanent_error = Some(e);
+                    failed += 1;
+                    ok = false;
+                    break;
+                }
+                Status::Transient(e) => {
+                    println!("Fail: {e}");
+                    failed += 1;
+                    ok = false;
+                }
+            }
+        }
+
+        if ok {
+            cache.succeeded(ips);
+        }
+    }
+
+    failures(failed)
+}
+
+/// Any server speaking the dyndns2 protocol: No-IP, Dyn, Strato, ChangeIP,
+/// self-hosted endpoints and others.
+#[derive(Deserialize)]
+pub struct Dyndns2Service {
+    name: Option<String>,
+    /// Update URL, e.g. `https://dynupdate.no-ip.com/nic/update`.
+    server: String,
+    /// Query parameter of the IPv6 address, for servers that do not take
+    /// it comma separated after the IPv4 one in `myip`.
+    ipv6_param: Option<String>,
+    user: String,
+    password: String,
+    domains: Vec<String>,
+}
+
+impl Service for Dyndns2Service {
+    fn label(&self) -> String {
+        label("dyndns2", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let endpoint = Endpoint {
+            url: &self.server,
+            host_param: "hostname",
+            ip_param: "myip",
+            ipv6_param: match &self.ipv6_param {
+                Some(param) => Ipv6Param::Named(param),
+                None => Ipv6Param::Joined,
+            },
+            user: &self.user,
+            password: &self.password,
+        };
+        update_domains(&self.label(), &endpoint, &self.domains, ips, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(ipv6_param: Ipv6Param) -> Endpoint {
+        Endpoint {
+            url: "https://example.com/nic/update",
+            host_param: "hostname",
+            ip_param: "myip",
+            ipv6_param,
+            user: "user",
+            password: "password",
+        }
+    }
+
+    fn both() -> Addresses {
+        Addresses {
+            ipv4: Some("192.0.2.1".parse().unwrap()),
+            ipv6: Some("2001:db8::1".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn parse_return_codes() {
+        assert!(matches!(parse("good 192.0.2.1"), Status::Success));
+        assert!(matches!(parse("nochg 192.0.2.1\n"), Status::Success));
+        assert!(matches!(parse("badauth"), Status::Permanent(_)));
+        assert!(matches!(parse("!yours"), Status::Permanent(_)));
+        assert!(matches!(parse("abuse"), Status::Permanent(_)));
+        assert!(matches!(parse("911"), Status::Transient(_)));
+        assert!(matches!(parse("<html>"), Status::Transient(_)));
+        assert!(matches!(parse(""), Status::Transient(_)));
+    }
+
+    #[test]
+    fn joined_addresses_in_one_request() {
+        assert_eq!(
+            endpoint(Ipv6Param::Joined).queries(&both()),
+            [[("myip", "192.0.2.1,2001:db8::1".to_string())]]
+        );
+
+        let ipv6 = Addresses {
+            ipv4: None,
+            ..both()
+        };
+        assert_eq!(
+            endpoint(Ipv6Param::Joined).queries(&ipv6),
+            [[("myip", "2001:db8::1".to_string())]]
+        );
+    }
+
+    #[test]
+    fn named_ipv6_parameter_in_one_request() {
+        assert_eq!(
+            endpoint(Ipv6Param::Named("myipv6")).queries(&both()),
+            [[
+                ("myip", "192.0.2.1".to_string()),
+                ("myipv6", "2001:db8::1".to_string())
+            ]]
+        );
+    }
+
+    #[test]
+    fn separate_requests() {
+        assert_eq!(
+            endpoint(Ipv6Param::Separate).queries(&both()),
+            [
+                [("myip", "192.0.2.1".to_string())],
+                [("myip", "2001:db8::1".to_string())]
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use super::{default_ttl, failures, label, send, split_domain, Domain, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+#[derive(Deserialize)]
+pub struct GandiService {
+    name: Option<String>,
+    /// Personal access token with the "Manage domain name technical
+    /// configurations" permission.
+    token: String,
+    /// TTL of the rrsets, at least 300.
+    #[serde(default = "default_ttl::<300>")]
+    ttl: u32,
+    domains: Vec<Domain>,
+}
+
+impl Service for GandiService {
+    fn label(&self) -> String {
+        label("Gandi", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let (sub, zone) = split_domain(&domain.name)?;
+            let rrset_name = match sub.is_empty() {
+                true => "@",
+                false => sub.as_str(),
+            };
+
+            let mut ok = true;
+            for record_type in ["A", "AAAA"] {
+                let ip = match ips.for_record(record_type) {
+                    Some(ip) => ip,
+                    None => continue,
+                };
+
+                // Replace the values of the rrset, creating it if needed
+                let resp = send(
+                    client
+                        .put(format!(
+                            "https://api.gandi.net/v5/livedns/domains/{zone}/records/{rrset_name}/{record_type}"
+                        ))
+                        .bearer_auth(&self.token)
+                        .header("Content-Type", "application/json")
+                        .body(
+                            json::object! {
+                                rrset_values: [ip.to_string()],
+                                rrset_ttl: domain.ttl.unwrap_or(self.ttl),
+                            }
+                            .dump(),
+                        ),
+                    &["message"],
+                    &[],
+                )?;
+
+                print!("{record_type} ");
+                match resp {
+                    Ok(_) => print!("Success "),
+                    Err((e, permanent)) => {
+                        if permanent {
+                            cache.permanent_error = Some(e.clone());
+                        }
+                        failed += 1;
+                        ok = false;
+                        print!("Fail: {e} ");
+                    }
+                }
+            }
+            println!();
+
+            if ok {
+                cache.succeeded(ips);
+            }
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,294 @@
+use super::{canonical, default_ttl, failures, label, send, ApiResult, Service};
+use crate::{ip::Addresses, jwt, state::ServiceState};
+use json::JsonValue;
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SCOPE: &str = "https://www.googleapis.com/auth/ndev.clouddns.readwrite";
+
+/// Google Cloud DNS, authenticated as a service account.
+#[derive(Deserialize)]
+pub struct GcloudDnsService {
+    name: Option<String>,
+    /// JSON key of a service account with the DNS Administrator role.
+    key_file: PathBuf,
+    /// Project of the zones, the one of the service account when unset.
+    project: Option<String>,
+    /// OAuth2 token endpoint, the one of the key file when unset.
+    token_url: Option<String>,
+    /// Base URL of the Cloud DNS API, overridable like token_url to test
+    /// against local stand-ins.
+    #[serde(default = "default_api_url")]
+    api_url: String,
+    #[serde(default = "default_ttl::<300>")]
+    ttl: u32,
+    domains: Vec<String>,
+}
+
+fn default_api_url() -> String {
+    "https://dns.googleapis.com/dns/v1".to_string()
+}
+
+impl GcloudDnsService {
+    fn key(&self) -> Result<JsonValue, Box<dyn Error>> {
+        let key = fs::read_to_string(&self.key_file)
+            .map_err(|e| format!("cannot read {}: {e}", self.key_file.display()))?;
+        Ok(json::parse(&key)?)
+    }
+
+    /// Exchanges a JWT signed with the service account key for an access
+    /// token, returning it with the project of the zones.
+    fn access_token(&self, client: &Client) -> Result<(String, String), Box<dyn Error>> {
+        let key = self.key()?;
+        let token_url = match &self.token_url {
+            Some(url) => url.clone(),
+            None => match key["token_uri"].as_str() {
+                Some(url) => url.to_string(),
+                None => "https://oauth2.googleapis.com/token".to_string(),
+            },
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let assertion = jwt::sign(
+            json::object! { kid: key["private_key_id"].clone() },
+            &json::object! {
+                iss: key["client_email"].clone(),
+                scope: SCOPE,
+                aud: token_url.as_str(),
+                iat: now,
+                exp: now + 3600,
+            },
+            &jwt::private_key(key["private_key"].as_str().unwrap_or(""))?,
+        )?;
+
+        let resp = client
+            .post(&token_url)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()?;
+        let status = resp.status();
+        let body = json::parse(&resp.text()?).unwrap_or(JsonValue::Null);
+
+        let token = match body["access_token"].as_str() {
+            Some(token) if status.is_success() => token.to_string(),
+            _ => match body["error_description"].as_str() {
+                Some(e) => return Err(format!("token request failed: {e}").into()),
+                None => return Err(format!("token request failed: HTTP status {status}").into()),
+            },
+        };
+
+        let project = match (&self.project, key["project_id"].as_str()) {
+            (Some(project), _) => project.clone(),
+            (None, Some(project)) => project.to_string(),
+            (None, None) => return Err("no project in the config or the key file".into()),
+        };
+
+        Ok((token, project))
+    }
+}
+
+/// Lists the public managed zones of the project, page by page.
+fn public_zones(
+    client: &Client,
+    zones_url: &str,
+    token: &str,
+) -> Result<ApiResult<Vec<JsonValue>>, Box<dyn Error>> {
+    let mut zones = vec![];
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let mut request = client.get(zones_url).bearer_auth(token);
+        if let Some(page_token) = &page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let resp = match send(request, &["error", "message"], &[])? {
+            Ok(resp) => resp,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        // A private zone may share its name with the public one
+        zones.extend(
+            resp["managedZones"]
+                .members()
+                .filter(|zone| zone["visibility"] != "private")
+                .cloned(),
+        );
+        match resp["nextPageToken"].as_str() {
+            Some(next) => page_token = Some(next.to_string()),
+            None => return Ok(Ok(zones)),
+        }
+    }
+}
+
+impl Service for GcloudDnsService {
+    fn label(&self) -> String {
+        label("CloudDNS", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        let key = self.key()?;
+        jwt::private_key(
+            key["private_key"]
+                .as_str()
+                .ok_or("key file has no private_key")?,
+        )?;
+        Ok(())
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let (token, project) = self.access_token(&client)?;
+        let zones_url = format!(
+            "{}/projects/{project}/managedZones",
+            self.api_url.trim_end_matches('/')
+        );
+        let mut zones: Option<Vec<JsonValue>> = None;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let name = canonical(domain);
+
+            // The managed zone is the public one with the longest name
+            // containing the domain, the zone list being fetched once per run
+            let zone = match &cache.zone_id {
+                Some(zone) => zone.clone(),
+                None => {
+                    if zones.is_none() {
+                        match public_zones(&client, &zones_url, &token)? {
+                            Ok(list) => zones = Some(list),
+                            Err((e, permanent)) => {
+                                println!("Fail: {e}");
+                                if permanent {
+                                    cache.permanent_error = Some(e);
+                                }
+                                failed += 1;
+                                continue;
+                            }
+                        }
+                    }
+
+                    let zone = zones.iter().flatten().filter(|zone| {
+                        zone["dnsName"]
+                            .as_str()
+                            .is_some_and(|dns| name == dns || name.ends_with(&format!(".{dns}")))
+                    });
+                    match zone.max_by_key(|zone| zone["dnsName"].as_str().map_or(0, str::len)) {
+                        Some(zone) => zone["name"].to_string(),
+                        None => {
+                            let e = "no managed zone contains this domain".to_string();
+                            println!("Fail: {e}");
+                            cache.permanent_error = Some(e);
+                            failed += 1;
+                            continue;
+                        }
+                    }
+                }
+            };
+            cache.zone_id = Some(zone.clone());
+
+            // Current rrsets, which must be deleted exactly as they are
+            let existing = match send(
+                client
+                    .get(format!("{zones_url}/{zone}/rrsets"))
+                    .bearer_auth(&token)
+                    .query(&[("name", &name)]),
+                &["error", "message"],
+                &[],
+            )? {
+                Ok(resp) => resp["rrsets"].clone(),
+                Err((e, _)) => {
+                    println!("Fail: {e}");
+                    failed += 1;
+                    cache.invalidate();
+                    continue;
+                }
+            };
+
+            let mut change = json::object! { additions: [], deletions: [] };
+            for record_type in ["A", "AAAA"] {
+                let ip = match ips.for_record(record_type) {
+                    Some(ip) => ip,
+                    None => continue,
+                };
+
+                let rrset = json::object! {
+                    name: name.as_str(),
+                    type: record_type,
+                    ttl: self.ttl,
+                    rrdatas: [ip.to_string()],
+                };
+                let current = existing
+                    .members()
+                    .find(|r| r["type"] == record_type)
+                    .cloned();
+
+                match current {
+                    Some(current)
+                        if current["ttl"] == rrset["ttl"]
+                            && current["rrdatas"] == rrset["rrdatas"] =>
+                    {
+                        continue
+                    }
+                    Some(current) => change["deletions"].push(current)?,
+                    None => {}
+                }
+                change["additions"].push(rrset)?;
+            }
+
+            if change["additions"].is_empty() {
+                println!("Success");
+                cache.succeeded(ips);
+                continue;
+            }
+
+            let resp = send(
+                client
+                    .post(format!("{zones_url}/{zone}/changes"))
+                    .bearer_auth(&token)
+                    .header("Content-Type", "application/json")
+                    .body(change.dump()),
+                &["error", "message"],
+                &[],
+            )?;
+
+            match resp {
+                Ok(_) => {
+                    println!("Success");
+                    cache.succeeded(ips);
+                }
+                Err((e, permanent)) => {
+                    println!("Fail: {e}");
+                    if permanent {
+                        cache.permanent_error = Some(e);
+                    }
+                    failed += 1;
+                    cache.invalidate();
+                }
+            }
+        }
+
+        failures(failed)
+    }
+}
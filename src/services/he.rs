@@ -0,0 +1,196 @@
+use super::{
+    all_unchanged,
+    dyndns2::{self, Status},
+    failures, label, Service,
+};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+/// Hurricane Electric: dyn.dns.he.net records, each with its own key, and
+/// optionally the client endpoint of a tunnelbroker.net tunnel.
+#[derive(Deserialize)]
+pub struct HeService {
+    name: Option<String>,
+    #[serde(default)]
+    domains: Vec<HeDomain>,
+    tunnel: Option<Tunnel>,
+}
+
+#[derive(Deserialize)]
+struct HeDomain {
+    name: String,
+    /// Key of the A record, and of the others unless set below.
+    key: String,
+    ipv6_key: Option<String>,
+    /// TXT record value, sent after every address update.
+    txt: Option<String>,
+    txt_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Tunnel {
+    /// Tunnel ID, from the tunnel details page.
+    id: String,
+    user: String,
+    /// Update key from the Advanced tab, the account password by default.
+    key: String,
+}
+
+/// Sends one dyndns2-style update and parses its response.
+fn update_request(
+    client: &Client,
+    url: &str,
+    user: &str,
+    key: &str,
+    query: &[(&str, &str)],
+) -> Result<Status, Box<dyn Error>> {
+    dyndns2::status(
+        client
+            .get(url)
+            .query(query)
+            .basic_auth(user, Some(key))
+            .send()?,
+    )
+}
+
+/// The addresses the tunnel follows: only IPv4, so only that one is compared
+/// and cached.
+fn tunnel_addresses(ips: &Addresses) -> Addresses {
+    Addresses {
+        ipv4: ips.ipv4,
+        ipv6: None,
+    }
+}
+
+impl HeService {
+    fn tunnel_entry(tunnel: &Tunnel) -> String {
+        format!("tunnel {}", tunnel.id)
+    }
+}
+
+impl Service for HeService {
+    fn label(&self) -> String {
+        label("HE", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn unchanged(&self, ips: &Addresses, state: &ServiceState) -> bool {
+        let tunnel = match &self.tunnel {
+            Some(tunnel) if ips.ipv4.is_some() => vec![Self::tunnel_entry(tunnel)],
+            _ => vec![],
+        };
+        all_unchanged(state, &self.domains(), ips)
+            && all_unchanged(state, &tunnel, &tunnel_addresses(ips))
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::builder()
+            .user_agent(concat!("dnsupdate/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let mut records = vec![];
+            if let Some(ip) = ips.ipv4 {
+                records.push(("A", "myip", ip.to_string(), &domain.key));
+            }
+            if let Some(ip) = ips.ipv6 {
+                let key = domain.ipv6_key.as_ref().unwrap_or(&domain.key);
+                records.push(("AAAA", "myip", ip.to_string(), key));
+            }
+            if let Some(txt) = &domain.txt {
+                let key = domain.txt_key.as_ref().unwrap_or(&domain.key);
+                records.push(("TXT", "txt", txt.clone(), key));
+            }
+
+            let mut ok = true;
+            for (record_type, param, value, key) in records {
+                let status = update_request(
+                    &client,
+                    "https://dyn.dns.he.net/nic/update",
+                    &domain.name,
+                    key,
+                    &[("hostname", &domain.name), (param, &value)],
+                )?;
+
+                print!("{record_type} ");
+                match status {
+                    Status::Success => print!("Success "),
+                    Status::Permanent(e) => {
+                        print!("Fail: {e} ");
+                        cache.permanent_error = Some(e);
+                        failed += 1;
+                        ok = false;
+                    }
+                    Status::Transient(e) => {
+                        print!("Fail: {e} ");
+                        failed += 1;
+                        ok = false;
+                    }
+                }
+            }
+            println!();
+
+            if ok {
+                cache.succeeded(ips);
+            }
+        }
+
+        if let (Some(tunnel), Some(ip)) = (&self.tunnel, ips.ipv4) {
+            let entry = Self::tunnel_entry(tunnel);
+            let cache = state.entry(entry.clone()).or_default();
+            let ipv4 = tunnel_addresses(ips);
+
+            match cache.skip_reason(&ipv4) {
+                Some(reason) => println!("{} Update {entry}: {reason}", self.label()),
+                None => {
+                    print!("{} Update {entry}: ", self.label());
+                    io::stdout().flush()?;
+
+                    let status = update_request(
+                        &client,
+                        "https://ipv4.tunnelbroker.net/nic/update",
+                        &tunnel.user,
+                        &tunnel.key,
+                        &[("hostname", &tunnel.id), ("myip", &ip.to_string())],
+                    )?;
+
+                    match status {
+                        Status::Success => {
+                            println!("Success");
+                            cache.succeeded(&ipv4);
+                        }
+                        Status::Permanent(e) => {
+                            println!("Fail: {e}");
+                            cache.permanent_error = Some(e);
+                            failed += 1;
+                        }
+                        Status::Transient(e) => {
+                            println!("Fail: {e}");
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,163 @@
+use super::{failures, label, send, split_domain, update_records, Domain, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+    net::IpAddr,
+};
+
+#[derive(Deserialize)]
+pub struct HetznerService {
+    name: Option<String>,
+    /// DNS console API token, sent as `Auth-API-Token`.
+    token: String,
+    #[serde(default)]
+    create_missing: bool,
+    /// TTL of created records, the zone default when unset.
+    ttl: Option<u32>,
+    domains: Vec<Domain>,
+}
+
+impl HetznerService {
+    fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("Auth-API-Token", &self.token)
+            .header("Content-Type", "application/json")
+    }
+}
+
+impl Service for HetznerService {
+    fn label(&self) -> String {
+        label("Hetzner", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let (sub, zone) = split_domain(&domain.name)?;
+            let record_name = match sub.is_empty() {
+                true => "@".to_string(),
+                false => sub,
+            };
+
+            // Get zone ID for current zone
+            let zone_id = match &cache.zone_id {
+                Some(zone_id) => zone_id.clone(),
+                None => match send(
+                    self.request(client.get("https://dns.hetzner.com/api/v1/zones"))
+                        .query(&[("name", &zone)]),
+                    &["error", "message"],
+                    &[],
+                )? {
+                    Ok(resp) if !resp["zones"].is_empty() => resp["zones"][0]["id"].to_string(),
+                    Ok(_) => {
+                        println!("Fail: zone not found");
+                        failed += 1;
+                        continue;
+                    }
+                    Err((e, _)) => {
+                        println!("Fail: {e}");
+                        failed += 1;
+                        continue;
+                    }
+                },
+            };
+            cache.zone_id = Some(zone_id.clone());
+
+            // Records of the zone, to find the ones of this domain
+            let records = match send(
+                self.request(client.get("https://dns.hetzner.com/api/v1/records"))
+                    .query(&[("zone_id", &zone_id)]),
+                &["error", "message"],
+                &[],
+            )? {
+                Ok(resp) => resp,
+                Err((e, _)) => {
+                    println!("Fail: {e}");
+                    failed += 1;
+                    cache.invalidate();
+                    continue;
+                }
+            };
+
+            let create_missing = domain.create_missing.unwrap_or(self.create_missing);
+
+            let body = |record_type: &str, ip: IpAddr| {
+                json::object! {
+                    value: ip.to_string(),
+                    type: record_type,
+                    name: record_name.as_str(),
+                    zone_id: zone_id.as_str(),
+                }
+            };
+
+            let domain_failed = update_records(
+                cache,
+                ips,
+                create_missing,
+                |record_type, _| {
+                    Ok(Ok(records["records"].members().find(|record| {
+                        record["type"] == record_type && record["name"] == record_name.as_str()
+                    })))
+                },
+                // Update the record, keeping its TTL
+                |record_type, record, ip| {
+                    let mut body = body(record_type, ip);
+                    if record["ttl"].is_number() {
+                        body["ttl"] = record["ttl"].clone();
+                    }
+                    let resp = send(
+                        self.request(client.put(format!(
+                            "https://dns.hetzner.com/api/v1/records/{}",
+                            record["id"]
+                        )))
+                        .body(body.dump()),
+                        &["error", "message"],
+                        &[],
+                    )?;
+                    Ok(resp.map(|_| None))
+                },
+                // Create the missing record
+                |record_type, ip| {
+                    let mut body = body(record_type, ip);
+                    if let Some(ttl) = domain.ttl.or(self.ttl) {
+                        body["ttl"] = ttl.into();
+                    }
+                    let resp = send(
+                        self.request(client.post("https://dns.hetzner.com/api/v1/records"))
+                            .body(body.dump()),
+                        &["error", "message"],
+                        &[],
+                    )?;
+                    Ok(resp.map(|_| None))
+                },
+            )?;
+            println!();
+
+            failed += domain_failed;
+            match domain_failed {
+                0 => cache.succeeded(ips),
+                _ => cache.invalidate(),
+            }
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,339 @@
+mod afraid;
+mod azure_dns;
+mod cloudflare;
+mod desec;
+mod digitalocean;
+mod duckdns;
+mod dyndns2;
+mod gandi;
+mod gcloud_dns;
+mod he;
+mod hetzner;
+mod namecheap;
+mod ovh;
+mod porkbun;
+mod powerdns;
+mod rfc2136;
+mod route53;
+mod ydns;
+
+pub use afraid::AfraidService;
+pub use azure_dns::AzureDnsService;
+pub use cloudflare::CloudflareService;
+pub use desec::DesecService;
+pub use digitalocean::DigitalOceanService;
+pub use duckdns::DuckDNSService;
+pub use dyndns2::Dyndns2Service;
+pub use gandi::GandiService;
+pub use gcloud_dns::GcloudDnsService;
+pub use he::HeService;
+pub use hetzner::HetznerService;
+pub use namecheap::NamecheapService;
+pub use ovh::OvhService;
+pub use porkbun::PorkbunService;
+pub use powerdns::PowerDnsService;
+pub use rfc2136::Rfc2136Service;
+pub use route53::Route53Service;
+pub use ydns::YDNSService;
+
+use crate::{
+    ip::Addresses,
+    state::{DomainState, ServiceState},
+};
+use json::JsonValue;
+use reqwest::{blocking::RequestBuilder, StatusCode};
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+        MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+use std::{error::Error, fmt, marker::PhantomData, net::IpAddr};
+use tldextract::{TldExtractor, TldOption};
+
+pub trait Service {
+    /// Name shown in front of every output line, e.g. `[Cloudflare:work]`.
+    /// The optional `name` of a provider section tells several accounts of
+    /// the provider apart, and the label keys their cached state.
+    fn label(&self) -> String;
+
+    /// Names of the configured domains.
+    fn domains(&self) -> Vec<String>;
+
+    /// Whether no domain needs an update to `ips`, so that the service is
+    /// skipped without even checking its credentials.
+    fn unchanged(&self, ips: &Addresses, state: &ServiceState) -> bool {
+        all_unchanged(state, &self.domains(), ips)
+    }
+
+    /// Checks the configuration and credentials before any update is made.
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Pushes the addresses to every configured domain. `state` holds what
+    /// was pushed on previous runs; domains already pointing to `ips` must be
+    /// skipped without any provider call.
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>>;
+}
+
+/// Builds a service label from the provider name and the optional instance
+/// name.
+fn label(provider: &str, name: &Option<String>) -> String {
+    match name {
+        Some(name) => format!("[{provider}:{name}]"),
+        None => format!("[{provider}]"),
+    }
+}
+
+/// Whether every domain of `domains` already points to `ips` or failed
+/// permanently, according to `state`.
+fn all_unchanged(state: &ServiceState, domains: &[String], ips: &Addresses) -> bool {
+    domains.iter().all(|domain| {
+        state
+            .get(domain)
+            .is_some_and(|d| d.skip_reason(ips).is_some())
+    })
+}
+
+/// A configured domain. In config files it is either a bare name or a table
+/// overriding the provider-wide record settings for that domain.
+#[derive(Deserialize)]
+#[serde(remote = "Self", deny_unknown_fields)]
+pub struct Domain {
+    pub name: String,
+    /// Whether to create A/AAAA records that do not exist yet, for providers
+    /// updating records one by one. Defaults to the `create_missing` of the
+    /// provider.
+    pub create_missing: Option<bool>,
+    pub ttl: Option<u32>,
+    pub proxied: Option<bool>,
+}
+
+/// Either shape is deserialized directly, rather than tried in turn, so that
+/// a wrong or unknown field of a table is reported by name.
+impl<'de> Deserialize<'de> for Domain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Domain, D::Error> {
+        struct DomainEntry;
+
+        impl<'de> Visitor<'de> for DomainEntry {
+            type Value = Domain;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a domain name or a table")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Domain, E> {
+                Ok(Domain {
+                    name: name.to_string(),
+                    create_missing: None,
+                    ttl: None,
+                    proxied: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Domain, A::Error> {
+                // The derived table shape
+                Domain::deserialize(MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(DomainEntry)
+    }
+}
+
+/// Splits a domain into the part below its registrable zone and the zone,
+/// e.g. `("www", "example.co.uk")`. The first part is empty at the apex.
+fn split_domain(domain: &str) -> Result<(String, String), Box<dyn Error>> {
+    let tld = TldExtractor::new(TldOption::default()).extract(domain)?;
+    match (tld.domain, tld.suffix) {
+        (Some(name), Some(suffix)) => Ok((
+            tld.subdomain.unwrap_or_default(),
+            format!("{name}.{suffix}"),
+        )),
+        _ => Err(format!("cannot find the zone of {domain}").into()),
+    }
+}
+
+/// Returns `name` as an absolute name, with the trailing dot.
+fn canonical(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+/// Default of the `ttl` field of a provider, `TTL` seconds.
+fn default_ttl<const TTL: u32>() -> u32 {
+    TTL
+}
+
+/// Outcome of an API call: its result, or the error message and whether it
+/// is permanent.
+type ApiResult<T> = Result<T, (String, bool)>;
+
+/// Sends a request to a JSON API and parses the response. Errors are read
+/// from the string at the `message` path of the body, else reported by HTTP
+/// status, and flagged as permanent for bad credentials and the `permanent`
+/// statuses.
+fn send(
+    request: RequestBuilder,
+    message: &[&str],
+    permanent: &[StatusCode],
+) -> Result<ApiResult<JsonValue>, Box<dyn Error>> {
+    let resp = request.send()?;
+    let status = resp.status();
+    let body = json::parse(&resp.text()?).unwrap_or(JsonValue::Null);
+    if status.is_success() {
+        return Ok(Ok(body));
+    }
+
+    let message = match message
+        .iter()
+        .fold(&body, |value, key| &value[*key])
+        .as_str()
+    {
+        Some(message) => message.to_string(),
+        None => format!("HTTP status {status}"),
+    };
+    let permanent = matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+        || permanent.contains(&status);
+    Ok(Err((message, permanent)))
+}
+
+/// Points the A/AAAA records of a domain to the detected addresses, one
+/// record at a time, for providers without a single call replacing them all.
+/// `lookup` finds the record of a type, given its cached ID if any; `update`
+/// points it to the address, and `create` adds it when it is missing and
+/// `create_missing` is set. Both return the record ID to cache, if the
+/// provider has one.
+///
+/// Prints the outcome of each record, without the final newline, and keeps
+/// the IDs and permanent errors in `cache`. Returns the number of failures,
+/// counting one when no record matches the detected addresses.
+fn update_records<R>(
+    cache: &mut DomainState,
+    ips: &Addresses,
+    create_missing: bool,
+    mut lookup: impl FnMut(&'static str, Option<&str>) -> Result<ApiResult<Option<R>>, Box<dyn Error>>,
+    mut update: impl FnMut(&'static str, R, IpAddr) -> Result<ApiResult<Option<String>>, Box<dyn Error>>,
+    mut create: impl FnMut(&'static str, IpAddr) -> Result<ApiResult<Option<String>>, Box<dyn Error>>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut failed = 0;
+    let mut updated = 0;
+    for record_type in ["A", "AAAA"] {
+        let ip = match ips.for_record(record_type) {
+            Some(ip) => ip,
+            None => continue,
+        };
+
+        let cached = cache.record_ids.get(record_type).map(String::as_str);
+        let resp = match lookup(record_type, cached)? {
+            Ok(Some(record)) => update(record_type, record, ip)?.map(|id| ("Success", id)),
+            Ok(None) if !create_missing => continue,
+            Ok(None) => create(record_type, ip)?.map(|id| ("Created", id)),
+            Err(e) => Err(e),
+        };
+
+        print!("{record_type} ");
+        match resp {
+            Ok((outcome, id)) => {
+                if let Some(id) = id {
+                    cache.record_ids.insert(record_type.to_string(), id);
+                }
+                print!("{outcome} ");
+            }
+            Err((e, permanent)) => {
+                print!("Fail: {e} ");
+                if permanent {
+                    cache.permanent_error = Some(e);
+                }
+                failed += 1;
+            }
+        }
+        updated += 1;
+    }
+
+    if updated == 0 {
+        print!("Fail: no A/AAAA record matches the detected addresses");
+        failed += 1;
+    }
+    Ok(failed)
+}
+
+/// Turns the number of failed updates of a run into its result.
+fn failures(failed: usize) -> Result<(), Box<dyn Error>> {
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{n} update(s) failed").into()),
+    }
+}
+
+/// Deserializes a provider section that is either a single table or an
+/// array of tables. Each shape is deserialized directly, so that errors
+/// inside a section still name the offending field.
+pub fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OneOrMany<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a table or an array of tables")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Vec<T>, A::Error> {
+            Ok(vec![T::deserialize(MapAccessDeserializer::new(map))?])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Vec<T>, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Section {
+        domains: Vec<Domain>,
+    }
+
+    #[test]
+    fn domain_names_and_tables() {
+        let section: Section = toml::from_str(
+            r#"domains = ["example.com", { name = "new.example.com", create_missing = true, ttl = 300 }]"#,
+        )
+        .unwrap();
+        assert_eq!(section.domains[0].name, "example.com");
+        assert_eq!(section.domains[0].ttl, None);
+        assert_eq!(section.domains[1].name, "new.example.com");
+        assert_eq!(section.domains[1].create_missing, Some(true));
+        assert_eq!(section.domains[1].ttl, Some(300));
+    }
+
+    #[test]
+    fn domain_table_errors_name_the_field() {
+        let e = toml::from_str::<Section>(r#"domains = [{ name = "example.com", ttl = "300" }]"#)
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("expected u32"), "{e}");
+
+        let e = toml::from_str::<Section>(
+            r#"domains = [{ name = "example.com", create_mising = true }]"#,
+        )
+        .err()
+        .unwrap();
+        assert!(
+            e.to_string().contains("unknown field `create_mising`"),
+            "{e}"
+        );
+    }
+}
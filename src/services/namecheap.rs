@@ -0,0 +1,130 @@
+use super::{failures, label, split_domain, Service};
+use crate::{ip::Addresses, state::ServiceState, xml};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+/// Namecheap dynamic DNS. The password is set per registered domain, so
+/// use one `[[namecheap]]` section per domain, each with its hosts.
+#[derive(Deserialize)]
+pub struct NamecheapService {
+    name: Option<String>,
+    /// Dynamic DNS password of the domain, from Advanced DNS in the
+    /// dashboard.
+    password: String,
+    domains: Vec<String>,
+}
+
+/// Reads the errors reported by an update response, empty on success.
+fn errors(body: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let count: usize = xml::text(body, "ErrCount")
+        .ok_or("invalid response")?
+        .parse()?;
+
+    Ok((1..=count)
+        .map(|i| xml::text(body, &format!("Err{i}")).unwrap_or_else(|| "unknown error".into()))
+        .collect())
+}
+
+impl Service for NamecheapService {
+    fn label(&self) -> String {
+        label("Namecheap", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            // Only A records can be updated this way
+            let ip = match ips.ipv4 {
+                Some(ip) => ip,
+                None => {
+                    println!("Fail: no IPv4 address detected");
+                    failed += 1;
+                    continue;
+                }
+            };
+
+            let (sub, zone) = split_domain(domain)?;
+            let host = match sub.is_empty() {
+                true => "@".to_string(),
+                false => sub,
+            };
+
+            let resp = client
+                .get("https://dynamicdns.park-your-domain.com/update")
+                .query(&[
+                    ("host", host.as_str()),
+                    ("domain", &zone),
+                    ("password", &self.password),
+                    ("ip", &ip.to_string()),
+                ])
+                .send()?
+                .error_for_status()?
+                .text()?;
+
+            let errors = errors(&resp)?;
+            if errors.is_empty() {
+                println!("Success");
+                cache.succeeded(ips);
+                continue;
+            }
+
+            let e = errors.join(", ");
+            println!("Fail: {e}");
+            // Wrong passwords and unknown hosts fail until the config is
+            // fixed
+            if errors
+                .iter()
+                .any(|e| e.contains("Passwords do not match") || e.contains("not found"))
+            {
+                cache.permanent_error = Some(e);
+            }
+            failed += 1;
+        }
+
+        failures(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn success() {
+        let body = r#"<?xml version="1.0"?><interface-response><Command>SETDNSHOST</Command><Language>eng</Language><IP>192.0.2.1</IP><ErrCount>0</ErrCount><ResponseCount>0</ResponseCount><Done>true</Done><debug><![CDATA[]]></debug></interface-response>"#;
+        assert!(errors(body).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reported_errors() {
+        let body = r#"<?xml version="1.0"?><interface-response><Command>SETDNSHOST</Command><Language>eng</Language><ErrCount>2</ErrCount><errors><Err1>Passwords do not match</Err1><Err2>Domain name not found</Err2></errors><ResponseCount>2</ResponseCount><Done>true</Done></interface-response>"#;
+        assert_eq!(
+            errors(body).unwrap(),
+            ["Passwords do not match", "Domain name not found"]
+        );
+    }
+
+    #[test]
+    fn invalid_response() {
+        assert!(errors("<html>Bad gateway</html>").is_err());
+    }
+}
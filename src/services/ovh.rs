@@ -0,0 +1,191 @@
+use super::{failures, label, send, split_domain, update_records, ApiResult, Domain, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use json::JsonValue;
+use reqwest::{blocking::Client, Method};
+use serde_derive::Deserialize;
+use sha1::{Digest, Sha1};
+use std::{
+    error::Error,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Deserialize)]
+pub struct OvhService {
+    name: Option<String>,
+    /// API endpoint: `eu`, `ca`, `us`, or a base URL.
+    #[serde(default = "default_endpoint")]
+    endpoint: String,
+    application_key: String,
+    application_secret: String,
+    /// Consumer key with GET/PUT/POST access to `/domain/zone/*`.
+    consumer_key: String,
+    #[serde(default)]
+    create_missing: bool,
+    /// TTL of updated and created records, the zone default when unset.
+    ttl: Option<u32>,
+    domains: Vec<Domain>,
+}
+
+fn default_endpoint() -> String {
+    "eu".to_string()
+}
+
+impl OvhService {
+    fn base_url(&self) -> &str {
+        match self.endpoint.as_str() {
+            "eu" => "https://eu.api.ovh.com/1.0",
+            "ca" => "https://ca.api.ovh.com/1.0",
+            "us" => "https://api.us.ovhcloud.com/1.0",
+            url => url.trim_end_matches('/'),
+        }
+    }
+
+    /// Difference between the server clock and ours, in seconds, since
+    /// signatures carry a timestamp the server checks.
+    fn time_delta(&self, client: &Client) -> Result<i64, Box<dyn Error>> {
+        let server: i64 = client
+            .get(format!("{}/auth/time", self.base_url()))
+            .send()?
+            .error_for_status()?
+            .text()?
+            .trim()
+            .parse()?;
+        let local = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        Ok(server - local)
+    }
+
+    /// Sends a signed request. `path` includes the query string, which is
+    /// part of the signed URL.
+    fn call(
+        &self,
+        client: &Client,
+        delta: i64,
+        method: Method,
+        path: &str,
+        body: Option<JsonValue>,
+    ) -> Result<ApiResult<JsonValue>, Box<dyn Error>> {
+        let url = format!("{}{path}", self.base_url());
+        let body = body.map(|b| b.dump()).unwrap_or_default();
+        let timestamp =
+            (SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64 + delta).to_string();
+
+        let signature = Sha1::digest(
+            [
+                self.application_secret.as_str(),
+                &self.consumer_key,
+                method.as_str(),
+                &url,
+                &body,
+                &timestamp,
+            ]
+            .join("+"),
+        );
+
+        let mut request = client
+            .request(method, &url)
+            .header("X-Ovh-Application", &self.application_key)
+            .header("X-Ovh-Consumer", &self.consumer_key)
+            .header("X-Ovh-Timestamp", timestamp)
+            .header("X-Ovh-Signature", format!("$1${}", hex::encode(signature)));
+        if !body.is_empty() {
+            request = request
+                .header("Content-Type", "application/json")
+                .body(body);
+        }
+
+        send(request, &["message"], &[])
+    }
+}
+
+impl Service for OvhService {
+    fn label(&self) -> String {
+        label("OVH", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let delta = self.time_delta(&client)?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let (sub, zone) = split_domain(&domain.name)?;
+            let records_path = format!("/domain/zone/{zone}/record");
+            let create_missing = domain.create_missing.unwrap_or(self.create_missing);
+            let ttl = domain.ttl.or(self.ttl);
+
+            let mut domain_failed = update_records(
+                cache,
+                ips,
+                create_missing,
+                // Find the record by subdomain and type, unless its ID is
+                // cached
+                |record_type, cached| {
+                    if let Some(record_id) = cached {
+                        return Ok(Ok(Some(record_id.to_string())));
+                    }
+                    let query = reqwest::Url::parse_with_params(
+                        "http://localhost",
+                        &[("fieldType", record_type), ("subDomain", &sub)],
+                    )?;
+                    let path = format!("{records_path}?{}", query.query().unwrap_or(""));
+                    let resp = self.call(&client, delta, Method::GET, &path, None)?;
+                    Ok(resp.map(|resp| resp[0].as_u64().map(|id| id.to_string())))
+                },
+                |_, record_id, ip| {
+                    let mut body = json::object! { target: ip.to_string() };
+                    if let Some(ttl) = ttl {
+                        body["ttl"] = ttl.into();
+                    }
+                    let path = format!("{records_path}/{record_id}");
+                    let resp = self.call(&client, delta, Method::PUT, &path, Some(body))?;
+                    Ok(resp.map(|_| Some(record_id)))
+                },
+                |record_type, ip| {
+                    let mut body = json::object! {
+                        fieldType: record_type,
+                        subDomain: sub.as_str(),
+                        target: ip.to_string(),
+                    };
+                    if let Some(ttl) = ttl {
+                        body["ttl"] = ttl.into();
+                    }
+                    let resp =
+                        self.call(&client, delta, Method::POST, &records_path, Some(body))?;
+                    Ok(resp.map(|resp| Some(resp["id"].to_string())))
+                },
+            )?;
+
+            // Changes are only served once the zone is refreshed
+            if domain_failed == 0 {
+                let path = format!("/domain/zone/{zone}/refresh");
+                if let Err((e, _)) = self.call(&client, delta, Method::POST, &path, None)? {
+                    print!("Refresh Fail: {e}");
+                    domain_failed += 1;
+                }
+            }
+            println!();
+
+            failed += domain_failed;
+            match domain_failed {
+                0 => cache.succeeded(ips),
+                _ => cache.invalidate(),
+            }
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,140 @@
+use super::{
+    default_ttl, failures, label, split_domain, update_records, ApiResult, Domain, Service,
+};
+use crate::{ip::Addresses, state::ServiceState};
+use json::JsonValue;
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+const API_URL: &str = "https://api.porkbun.com/api/json/v3";
+
+#[derive(Deserialize)]
+pub struct PorkbunService {
+    name: Option<String>,
+    api_key: String,
+    secret_api_key: String,
+    #[serde(default)]
+    create_missing: bool,
+    /// TTL of updated and created records, at least 600.
+    #[serde(default = "default_ttl::<600>")]
+    ttl: u32,
+    domains: Vec<Domain>,
+}
+
+/// Unwraps the `status` envelope of a response, turning errors into their
+/// message.
+fn parse(body: &str) -> Result<JsonValue, String> {
+    let body = json::parse(body).map_err(|_| "invalid response".to_string())?;
+    match body["status"].as_str() {
+        Some("SUCCESS") => Ok(body),
+        _ => Err(match body["message"].as_str() {
+            Some(message) => message.to_string(),
+            None => "unknown error".to_string(),
+        }),
+    }
+}
+
+impl PorkbunService {
+    /// Posts to an API path, with the keys added to the JSON body.
+    fn call(
+        &self,
+        client: &Client,
+        path: &str,
+        mut body: JsonValue,
+    ) -> Result<ApiResult<JsonValue>, Box<dyn Error>> {
+        body["apikey"] = self.api_key.as_str().into();
+        body["secretapikey"] = self.secret_api_key.as_str().into();
+
+        let resp = client
+            .post(format!("{API_URL}{path}"))
+            .header("Content-Type", "application/json")
+            .body(body.dump())
+            .send()?;
+        Ok(parse(&resp.text()?).map_err(|e| (e, false)))
+    }
+}
+
+impl Service for PorkbunService {
+    fn label(&self) -> String {
+        label("Porkbun", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.iter().map(|d| d.name.clone()).collect()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        self.call(&Client::new(), "/ping", json::object! {})?
+            .map_err(|(e, _)| format!("API keys rejected: {e}"))?;
+        Ok(())
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.name.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {}: {reason}", self.label(), domain.name);
+                continue;
+            }
+
+            print!("{} Update {}: ", self.label(), domain.name);
+            io::stdout().flush()?;
+
+            let (sub, zone) = split_domain(&domain.name)?;
+            let create_missing = domain.create_missing.unwrap_or(self.create_missing);
+            let ttl = domain.ttl.unwrap_or(self.ttl).to_string();
+
+            let domain_failed = update_records(
+                cache,
+                ips,
+                create_missing,
+                // Editing a record that does not exist succeeds without
+                // creating it, so look it up first
+                |record_type, _| {
+                    let resp = self.call(
+                        &client,
+                        &format!("/dns/retrieveByNameType/{zone}/{record_type}/{sub}"),
+                        json::object! {},
+                    )?;
+                    Ok(resp.map(|resp| (!resp["records"].is_empty()).then_some(())))
+                },
+                |record_type, (), ip| {
+                    let resp = self.call(
+                        &client,
+                        &format!("/dns/editByNameType/{zone}/{record_type}/{sub}"),
+                        json::object! { content: ip.to_string(), ttl: ttl.as_str() },
+                    )?;
+                    Ok(resp.map(|_| None))
+                },
+                |record_type, ip| {
+                    let resp = self.call(
+                        &client,
+                        &format!("/dns/create/{zone}"),
+                        json::object! {
+                            name: sub.as_str(),
+                            type: record_type,
+                            content: ip.to_string(),
+                            ttl: ttl.as_str(),
+                        },
+                    )?;
+                    Ok(resp.map(|_| None))
+                },
+            )?;
+            println!();
+
+            failed += domain_failed;
+            if domain_failed == 0 {
+                cache.succeeded(ips);
+            }
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,148 @@
+use super::{canonical, default_ttl, failures, label, send, split_domain, ApiResult, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use json::JsonValue;
+use reqwest::{blocking::Client, StatusCode};
+use serde_derive::Deserialize;
+use std::{
+    error::Error,
+    io::{self, Write},
+};
+
+/// Statuses of unknown zones and out of zone names, besides bad keys.
+const PERMANENT: [StatusCode; 2] = [StatusCode::NOT_FOUND, StatusCode::UNPROCESSABLE_ENTITY];
+
+/// PowerDNS Authoritative server, through its HTTP API.
+#[derive(Deserialize)]
+pub struct PowerDnsService {
+    name: Option<String>,
+    /// Base URL of the API, the webserver address of pdns.
+    url: String,
+    api_key: String,
+    #[serde(default = "default_server")]
+    server: String,
+    /// Zone of the domains, guessed from the public suffix list when unset.
+    zone: Option<String>,
+    #[serde(default = "default_ttl::<300>")]
+    ttl: u32,
+    /// Rectify the zone after each update, for DNSSEC signed zones not set
+    /// to rectify by themselves.
+    #[serde(default)]
+    rectify: bool,
+    /// Send a NOTIFY to the secondaries after each update.
+    #[serde(default)]
+    notify: bool,
+    domains: Vec<String>,
+}
+
+fn default_server() -> String {
+    "localhost".to_string()
+}
+
+impl PowerDnsService {
+    fn zone_url(&self, zone: &str) -> String {
+        format!(
+            "{}/api/v1/servers/{}/zones/{}",
+            self.url.trim_end_matches('/'),
+            self.server,
+            canonical(zone)
+        )
+    }
+
+    /// Replaces the A/AAAA rrsets of a domain in one request.
+    fn replace(
+        &self,
+        client: &Client,
+        zone_url: &str,
+        domain: &str,
+        ips: &Addresses,
+    ) -> Result<ApiResult<JsonValue>, Box<dyn Error>> {
+        let mut rrsets = JsonValue::new_array();
+        for record_type in ["A", "AAAA"] {
+            if let Some(ip) = ips.for_record(record_type) {
+                rrsets.push(json::object! {
+                    name: canonical(domain),
+                    type: record_type,
+                    ttl: self.ttl,
+                    changetype: "REPLACE",
+                    records: [{ content: ip.to_string(), disabled: false }],
+                })?;
+            }
+        }
+
+        send(
+            client
+                .patch(zone_url)
+                .header("X-API-Key", &self.api_key)
+                .header("Content-Type", "application/json")
+                .body(json::object! { rrsets: rrsets }.dump()),
+            &["error"],
+            &PERMANENT,
+        )
+    }
+}
+
+impl Service for PowerDnsService {
+    fn label(&self) -> String {
+        label("PowerDNS", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let zone = match &self.zone {
+                Some(zone) => zone.clone(),
+                None => split_domain(domain)?.1,
+            };
+            let zone_url = self.zone_url(&zone);
+
+            if let Err((e, permanent)) = self.replace(&client, &zone_url, domain, ips)? {
+                println!("Fail: {e}");
+                if permanent {
+                    cache.permanent_error = Some(e);
+                }
+                failed += 1;
+                continue;
+            }
+            print!("Success");
+
+            // The records are in place, so a failure here is only reported, not
+            // counted
+            for (enabled, action) in [(self.rectify, "rectify"), (self.notify, "notify")] {
+                if !enabled {
+                    continue;
+                }
+                let resp = send(
+                    client
+                        .put(format!("{zone_url}/{action}"))
+                        .header("X-API-Key", &self.api_key),
+                    &["error"],
+                    &PERMANENT,
+                )?;
+                match resp {
+                    Ok(_) => print!(", {action} Success"),
+                    Err((e, _)) => print!(", {action} Fail: {e}"),
+                }
+            }
+            println!();
+
+            cache.succeeded(ips);
+        }
+
+        failures(failed)
+    }
+}
@@ -0,0 +1,388 @@
+use super::{default_ttl, failures, label, Service};
+use crate::{ip::Addresses, state::ServiceState};
+use hmac::{Hmac, Mac};
+use serde_derive::Deserialize;
+use sha2::{Sha256, Sha512};
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const TYPE_A: u16 = 1;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+const OPCODE_UPDATE: u16 = 5;
+
+/// Seconds of clock difference the server may accept on signatures.
+const FUDGE: u16 = 300;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum Algorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::HmacSha256 => "hmac-sha256",
+            Algorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn sign(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::HmacSha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Algorithm::HmacSha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// Dynamic updates (RFC 2136) signed with TSIG (RFC 8945), for self-hosted
+/// BIND, Knot or PowerDNS servers.
+#[derive(Deserialize)]
+pub struct Rfc2136Service {
+    name: Option<String>,
+    /// Primary server, as `host` or `host:port`.
+    server: String,
+    zone: String,
+    key_name: String,
+    #[serde(default = "default_algorithm")]
+    key_algorithm: Algorithm,
+    /// Base64 key secret, as in the `secret` of a BIND key statement.
+    key_secret: String,
+    #[serde(default = "default_ttl::<300>")]
+    ttl: u32,
+    /// Send updates over TCP instead of UDP.
+    #[serde(default)]
+    tcp: bool,
+    domains: Vec<String>,
+}
+
+fn default_algorithm() -> Algorithm {
+    Algorithm::HmacSha256
+}
+
+/// Appends a domain name in uncompressed wire format.
+fn push_name(buf: &mut Vec<u8>, name: &str) -> Result<(), Box<dyn Error>> {
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        if label.len() > 63 {
+            return Err(format!("label {label:?} is longer than 63 bytes").into());
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// Appends a resource record.
+fn push_record(
+    buf: &mut Vec<u8>,
+    name: &str,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    rdata: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    push_name(buf, name)?;
+    buf.extend_from_slice(&rtype.to_be_bytes());
+    buf.extend_from_slice(&class.to_be_bytes());
+    buf.extend_from_slice(&ttl.to_be_bytes());
+    buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    buf.extend_from_slice(rdata);
+    Ok(())
+}
+
+/// Describes a DNS response code.
+fn rcode_name(rcode: u8) -> &'static str {
+    match rcode {
+        1 => "FORMERR, the server could not parse the update",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP, the server does not support updates",
+        5 => "REFUSED, the key may not update this zone",
+        8 => "NXRRSET",
+        9 => "NOTAUTH, the key or the clock is wrong, or the server is not authoritative",
+        10 => "NOTZONE, the domain is not in the zone",
+        _ => "unknown error",
+    }
+}
+
+impl Rfc2136Service {
+    /// Builds a signed UPDATE message replacing the A/AAAA RRsets of
+    /// `domain` with the detected addresses. `time` is the signing time, in
+    /// seconds since the epoch.
+    fn message(
+        &self,
+        domain: &str,
+        ips: &Addresses,
+        id: u16,
+        time: u64,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let secret = base64::decode(&self.key_secret)?;
+
+        let records: Vec<(u16, IpAddr)> = [
+            (TYPE_A, ips.for_record("A")),
+            (TYPE_AAAA, ips.for_record("AAAA")),
+        ]
+        .into_iter()
+        .filter_map(|(rtype, ip)| Some((rtype, ip?)))
+        .collect();
+
+        let mut msg = vec![];
+        msg.extend_from_slice(&id.to_be_bytes());
+        msg.extend_from_slice(&(OPCODE_UPDATE << 11).to_be_bytes());
+        // One zone, no prerequisites, two updates per record type
+        for count in [1, 0, 2 * records.len() as u16, 0] {
+            msg.extend_from_slice(&count.to_be_bytes());
+        }
+
+        push_name(&mut msg, &self.zone)?;
+        msg.extend_from_slice(&TYPE_SOA.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        for (rtype, ip) in &records {
+            // Delete the whole RRset, then add the new address
+            push_record(&mut msg, domain, *rtype, CLASS_ANY, 0, &[])?;
+            let rdata = match ip {
+                IpAddr::V4(ip) => ip.octets().to_vec(),
+                IpAddr::V6(ip) => ip.octets().to_vec(),
+            };
+            push_record(&mut msg, domain, *rtype, CLASS_IN, self.ttl, &rdata)?;
+        }
+
+        // TSIG variables, signed after the message itself
+        let mut key_name = vec![];
+        push_name(&mut key_name, &self.key_name.to_lowercase())?;
+        let mut algorithm = vec![];
+        push_name(&mut algorithm, self.key_algorithm.name())?;
+
+        let mut signed = msg.clone();
+        signed.extend_from_slice(&key_name);
+        signed.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        signed.extend_from_slice(&0u32.to_be_bytes());
+        signed.extend_from_slice(&algorithm);
+        signed.extend_from_slice(&time.to_be_bytes()[2..]);
+        signed.extend_from_slice(&FUDGE.to_be_bytes());
+        // No error, no other data
+        signed.extend_from_slice(&[0, 0, 0, 0]);
+        let mac = self.key_algorithm.sign(&secret, &signed);
+
+        let mut rdata = algorithm;
+        rdata.extend_from_slice(&time.to_be_bytes()[2..]);
+        rdata.extend_from_slice(&FUDGE.to_be_bytes());
+        rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&mac);
+        rdata.extend_from_slice(&id.to_be_bytes());
+        rdata.extend_from_slice(&[0, 0, 0, 0]);
+        push_record(
+            &mut msg,
+            &self.key_name.to_lowercase(),
+            TYPE_TSIG,
+            CLASS_ANY,
+            0,
+            &rdata,
+        )?;
+        // The TSIG record is the only additional record
+        msg[10..12].copy_from_slice(&1u16.to_be_bytes());
+
+        Ok(msg)
+    }
+
+    fn server_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        let mut addrs = match self.server.to_socket_addrs() {
+            Ok(addrs) => addrs,
+            Err(_) => (self.server.as_str(), 53).to_socket_addrs()?,
+        };
+        Ok(addrs.next().ok_or("server has no address")?)
+    }
+
+    /// Sends the message and returns the response, over UDP unless TCP is
+    /// configured or the UDP response was truncated.
+    fn send(&self, msg: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let server = self.server_addr()?;
+        let timeout = Some(Duration::from_secs(5));
+
+        if !self.tcp {
+            let socket = UdpSocket::bind(match server {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            })?;
+            socket.set_read_timeout(timeout)?;
+            socket.connect(server)?;
+            socket.send(msg)?;
+
+            let mut buf = [0; 4096];
+            let len = socket.recv(&mut buf)?;
+            // Unless the TC bit is set
+            if len < 3 || buf[2] & 0x02 == 0 {
+                return Ok(buf[..len].to_vec());
+            }
+        }
+
+        let mut stream = TcpStream::connect_timeout(&server, Duration::from_secs(5))?;
+        stream.set_read_timeout(timeout)?;
+        stream.write_all(&(msg.len() as u16).to_be_bytes())?;
+        stream.write_all(msg)?;
+
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl Service for Rfc2136Service {
+    fn label(&self) -> String {
+        label("RFC2136", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        base64::decode(&self.key_secret).map_err(|e| format!("invalid key_secret: {e}"))?;
+        Ok(())
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            let id = (now.subsec_nanos() >> 8) as u16;
+            let msg = self.message(domain, ips, id, now.as_secs())?;
+            let resp = self.send(&msg)?;
+
+            if resp.len() < 12 || resp[..2] != msg[..2] {
+                println!("Fail: invalid response");
+                failed += 1;
+                continue;
+            }
+
+            match resp[3] & 0x0f {
+                0 => {
+                    println!("Success");
+                    cache.succeeded(ips);
+                }
+                rcode @ (5 | 10) => {
+                    println!("Fail: {}", rcode_name(rcode));
+                    cache.permanent_error = Some(rcode_name(rcode).to_string());
+                    failed += 1;
+                }
+                rcode => {
+                    println!("Fail: {}", rcode_name(rcode));
+                    failed += 1;
+                }
+            }
+        }
+
+        failures(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> Rfc2136Service {
+        Rfc2136Service {
+            name: None,
+            server: "127.0.0.1".to_string(),
+            zone: "example.com".to_string(),
+            key_name: "DNSupdate".to_string(),
+            key_algorithm: Algorithm::HmacSha256,
+            key_secret: "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".to_string(),
+            ttl: 300,
+            tcp: false,
+            domains: vec![],
+        }
+    }
+
+    #[test]
+    fn names_in_wire_format() {
+        let mut buf = vec![];
+        push_name(&mut buf, "host.Example.com.").unwrap();
+        assert_eq!(buf, b"\x04host\x07Example\x03com\x00");
+
+        let mut buf = vec![];
+        push_name(&mut buf, ".").unwrap();
+        assert_eq!(buf, [0]);
+
+        assert!(push_name(&mut vec![], &"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn signed_update() {
+        let ips = Addresses {
+            ipv4: Some("192.0.2.1".parse().unwrap()),
+            ipv6: Some("2001:db8::1".parse().unwrap()),
+        };
+        let msg = service()
+            .message("host.example.com", &ips, 0x1234, 1_700_000_000)
+            .unwrap();
+
+        // RFC 2136 header, zone and update sections followed by the RFC 8945
+        // TSIG record, with the MAC computed independently over the message
+        // and the TSIG variables
+        let expected = concat!(
+            "123428000001000000040001",
+            "076578616d706c6503636f6d0000060001",
+            "04686f7374076578616d706c6503636f6d00000100ff000000000000",
+            "04686f7374076578616d706c6503636f6d00000100010000012c0004c0000201",
+            "04686f7374076578616d706c6503636f6d00001c00ff000000000000",
+            "04686f7374076578616d706c6503636f6d00001c00010000012c0010",
+            "20010db8000000000000000000000001",
+            "09646e737570646174650000fa00ff00000000003d",
+            "0b686d61632d73686132353600",
+            "00006553f100012c0020",
+            "e4580b296656af66f12fe7f79bd87c2f21236f47012592353fbf9532ddd0a576",
+            "123400000000",
+        );
+        assert_eq!(hex::encode(msg), expected);
+    }
+
+    #[test]
+    fn update_of_one_family() {
+        let ips = Addresses {
+            ipv4: Some("192.0.2.1".parse().unwrap()),
+            ipv6: None,
+        };
+        let msg = service()
+            .message("host.example.com", &ips, 1, 1_700_000_000)
+            .unwrap();
+
+        // One zone, no prerequisites, delete and add, one TSIG record
+        assert_eq!(msg[4..12], [0, 1, 0, 0, 0, 2, 0, 1]);
+    }
+}
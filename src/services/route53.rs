@@ -0,0 +1,534 @@
+use super::{default_ttl, failures, label, split_domain, Service};
+use crate::{ip::Addresses, state::ServiceState, xml};
+use hmac::{Hmac, Mac};
+use reqwest::{blocking::Client, Method};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Route 53 is a global service, signed for this region.
+const REGION: &str = "us-east-1";
+
+/// Error codes that will not go away by retrying.
+const PERMANENT_ERRORS: [&str; 4] = [
+    "AccessDenied",
+    "InvalidClientTokenId",
+    "NoSuchHostedZone",
+    "InvalidChangeBatch",
+];
+
+#[derive(Deserialize)]
+pub struct Route53Service {
+    name: Option<String>,
+    /// Credentials, read from the environment or the shared credentials
+    /// file when unset.
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+    session_token: Option<String>,
+    /// Profile of the shared credentials file, `AWS_PROFILE` or `default`
+    /// when unset.
+    profile: Option<String>,
+    #[serde(default = "default_endpoint")]
+    endpoint: String,
+    #[serde(default = "default_ttl::<300>")]
+    ttl: u32,
+    domains: Vec<String>,
+}
+
+fn default_endpoint() -> String {
+    "https://route53.amazonaws.com".to_string()
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// Formats a Unix time as the `YYYYMMDD` date and `YYYYMMDDTHHMMSSZ`
+/// timestamp used by SigV4.
+fn amz_date(secs: u64) -> (String, String) {
+    // Civil date from days since the epoch, after Howard Hinnant
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{year:04}{month:02}{day:02}");
+    let time = secs % 86400;
+    let timestamp = format!(
+        "{date}T{:02}{:02}{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    );
+    (date, timestamp)
+}
+
+/// Percent-encodes everything but the unreserved characters, as SigV4
+/// canonical requests require.
+fn uri_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Encodes and sorts query parameters into a SigV4 canonical query string.
+fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut query: Vec<String> = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k), uri_encode(v)))
+        .collect();
+    query.sort();
+    query.join("&")
+}
+
+/// Builds a SigV4 canonical request. `headers` are the signed headers, with
+/// lowercase names sorted in order.
+fn canonical_request(
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(&str, String)],
+    body: &str,
+) -> String {
+    let names: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+    format!(
+        "{method}\n{path}\n{query}\n{}\n{}\n{}",
+        headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect::<String>(),
+        names.join(";"),
+        hex::encode(Sha256::digest(body.as_bytes()))
+    )
+}
+
+/// Signs a canonical request made at `timestamp`, returning the credential
+/// scope and the hex signature.
+fn sign(
+    secret_access_key: &str,
+    timestamp: &str,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> (String, String) {
+    let date = &timestamp[..8];
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let mut key = format!("AWS4{secret_access_key}").into_bytes();
+    for part in [date, region, service, "aws4_request"] {
+        key = hmac_sha256(&key, part);
+    }
+    (scope, hex::encode(hmac_sha256(&key, &string_to_sign)))
+}
+
+/// Reads a profile of an INI-style AWS shared credentials file.
+fn shared_credentials(path: &PathBuf, profile: &str) -> Option<Credentials> {
+    let file = fs::read_to_string(path).ok()?;
+
+    let mut in_profile = false;
+    let mut values = HashMap::new();
+    for line in file.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_profile = line.trim_matches(|c| c == '[' || c == ']').trim() == profile;
+        } else if let (true, Some((key, value))) = (in_profile, line.split_once('=')) {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+
+    Some(Credentials {
+        access_key_id: values.remove("aws_access_key_id")?,
+        secret_access_key: values.remove("aws_secret_access_key")?,
+        session_token: values.remove("aws_session_token"),
+    })
+}
+
+impl Route53Service {
+    /// Finds credentials in the config, then the environment, then the
+    /// shared credentials file.
+    fn credentials(&self) -> Result<Credentials, Box<dyn Error>> {
+        if let (Some(id), Some(secret)) = (&self.access_key_id, &self.secret_access_key) {
+            return Ok(Credentials {
+                access_key_id: id.clone(),
+                secret_access_key: secret.clone(),
+                session_token: self.session_token.clone(),
+            });
+        }
+
+        if let (Ok(id), Ok(secret)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Credentials {
+                access_key_id: id,
+                secret_access_key: secret,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+
+        let path = match env::var("AWS_SHARED_CREDENTIALS_FILE") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".aws/credentials"),
+        };
+        let profile = match &self.profile {
+            Some(profile) => profile.clone(),
+            None => env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string()),
+        };
+        shared_credentials(&path, &profile)
+            .ok_or_else(|| format!("no AWS credentials found for profile {profile}").into())
+    }
+
+    /// Sends a request signed with SigV4 and returns whether it succeeded
+    /// along with the response body.
+    fn send(
+        &self,
+        client: &Client,
+        credentials: &Credentials,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: String,
+    ) -> Result<(bool, String), Box<dyn Error>> {
+        let endpoint = reqwest::Url::parse(&self.endpoint)?;
+        let host = match endpoint.port() {
+            Some(port) => format!("{}:{port}", endpoint.host_str().unwrap_or("")),
+            None => endpoint.host_str().unwrap_or("").to_string(),
+        };
+
+        let query = canonical_query(query);
+        let (_, timestamp) = amz_date(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+
+        let mut headers = vec![("host", host), ("x-amz-date", timestamp.clone())];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        let signed_headers = signed_headers.join(";");
+
+        let canonical_request = canonical_request(method.as_str(), path, &query, &headers, &body);
+        let (scope, signature) = sign(
+            &credentials.secret_access_key,
+            &timestamp,
+            REGION,
+            "route53",
+            &canonical_request,
+        );
+
+        let mut url = format!("{}{path}", self.endpoint.trim_end_matches('/'));
+        if !query.is_empty() {
+            url = format!("{url}?{query}");
+        }
+
+        let mut request = client
+            .request(method, url)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                    credentials.access_key_id
+                ),
+            )
+            .body(body);
+        for (name, value) in headers.into_iter().skip(1) {
+            request = request.header(name, value);
+        }
+
+        let resp = request.send()?;
+        Ok((resp.status().is_success(), resp.text()?))
+    }
+
+    /// Looks up the ID of the public hosted zone named `zone`.
+    fn zone_id(
+        &self,
+        client: &Client,
+        credentials: &Credentials,
+        zone: &str,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let (ok, resp) = self.send(
+            client,
+            credentials,
+            Method::GET,
+            "/2013-04-01/hostedzonesbyname",
+            &[("dnsname", zone), ("maxitems", "10")],
+            String::new(),
+        )?;
+        match ok {
+            true => Ok(public_zone_id(&resp, zone)),
+            false => Err(error_message(&resp).into()),
+        }
+    }
+}
+
+/// Finds the ID of the public hosted zone named `zone` in a
+/// `ListHostedZonesByName` response, which also lists the zones following it
+/// and private zones of the same name.
+fn public_zone_id(resp: &str, zone: &str) -> Option<String> {
+    xml::elements(resp, "HostedZone")
+        .into_iter()
+        .filter(|hosted_zone| {
+            xml::text(hosted_zone, "Name").as_deref() == Some(&format!("{zone}."))
+                && xml::text(hosted_zone, "PrivateZone").as_deref() != Some("true")
+        })
+        .find_map(|hosted_zone| xml::text(hosted_zone, "Id"))
+        .map(|id| id.trim_start_matches("/hostedzone/").to_string())
+}
+
+/// Builds the `ChangeResourceRecordSets` request upserting the A/AAAA records
+/// of `domain`.
+fn change_batch(domain: &str, ips: &Addresses, ttl: u32) -> String {
+    let changes: String = ["A", "AAAA"]
+        .iter()
+        .filter_map(|record_type| Some((record_type, ips.for_record(record_type)?)))
+        .map(|(record_type, ip)| {
+            format!(
+                "<Change><Action>UPSERT</Action><ResourceRecordSet><Name>{}</Name><Type>{record_type}</Type><TTL>{ttl}</TTL><ResourceRecords><ResourceRecord><Value>{ip}</Value></ResourceRecord></ResourceRecords></ResourceRecordSet></Change>",
+                xml::escape(domain),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ChangeResourceRecordSetsRequest xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><ChangeBatch><Comment>dnsupdate</Comment><Changes>{changes}</Changes></ChangeBatch></ChangeResourceRecordSetsRequest>"#
+    )
+}
+
+/// Extracts the code and message of a Route 53 error response.
+fn error_message(resp: &str) -> String {
+    if let Some(batch) = xml::elements(resp, "InvalidChangeBatch").first() {
+        let messages: Vec<String> = xml::elements(batch, "Message")
+            .iter()
+            .map(|message| xml::unescape(message.trim()))
+            .collect();
+        return format!("InvalidChangeBatch: {}", messages.join(", "));
+    }
+
+    match (xml::text(resp, "Code"), xml::text(resp, "Message")) {
+        (Some(code), Some(message)) => format!("{code}: {message}"),
+        (Some(code), None) => code,
+        (None, Some(message)) => message,
+        (None, None) => "unexpected response".to_string(),
+    }
+}
+
+impl Service for Route53Service {
+    fn label(&self) -> String {
+        label("Route53", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        self.credentials().map(|_| ())
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let client = Client::new();
+        let credentials = self.credentials()?;
+        let mut failed = 0;
+
+        for domain in &self.domains {
+            let cache = state.entry(domain.clone()).or_default();
+            if let Some(reason) = cache.skip_reason(ips) {
+                println!("{} Update {domain}: {reason}", self.label());
+                continue;
+            }
+
+            print!("{} Update {domain}: ", self.label());
+            io::stdout().flush()?;
+
+            let zone_id = match &cache.zone_id {
+                Some(zone_id) => zone_id.clone(),
+                None => {
+                    let (_, zone) = split_domain(domain)?;
+                    match self.zone_id(&client, &credentials, &zone)? {
+                        Some(zone_id) => zone_id,
+                        None => {
+                            println!("Fail: hosted zone {zone} not found");
+                            failed += 1;
+                            continue;
+                        }
+                    }
+                }
+            };
+            cache.zone_id = Some(zone_id.clone());
+
+            let (ok, resp) = self.send(
+                &client,
+                &credentials,
+                Method::POST,
+                &format!("/2013-04-01/hostedzone/{zone_id}/rrset"),
+                &[],
+                change_batch(domain, ips, self.ttl),
+            )?;
+
+            if ok {
+                println!("Success");
+                cache.succeeded(ips);
+                continue;
+            }
+
+            let e = error_message(&resp);
+            println!("Fail: {e}");
+            failed += 1;
+            cache.invalidate();
+            if PERMANENT_ERRORS.iter().any(|code| e.starts_with(code)) {
+                cache.permanent_error = Some(e);
+            }
+        }
+
+        failures(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Credentials and time of the AWS Signature Version 4 test suite
+    const SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const TIMESTAMP: &str = "20150830T123600Z";
+
+    fn headers() -> Vec<(&'static str, String)> {
+        vec![
+            ("host", "example.amazonaws.com".to_string()),
+            ("x-amz-date", TIMESTAMP.to_string()),
+        ]
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            amz_date(1_440_938_160),
+            ("20150830".to_string(), TIMESTAMP.to_string())
+        );
+        assert_eq!(
+            amz_date(0),
+            ("19700101".to_string(), "19700101T000000Z".to_string())
+        );
+        // Leap day
+        assert_eq!(amz_date(951_782_400).0, "20000229");
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(uri_encode("AZaz09-_.~"), "AZaz09-_.~");
+        assert_eq!(uri_encode("a b/c=d&é"), "a%20b%2Fc%3Dd%26%C3%A9");
+        assert_eq!(
+            canonical_query(&[("maxitems", "10"), ("dnsname", "example.com")]),
+            "dnsname=example.com&maxitems=10"
+        );
+    }
+
+    #[test]
+    fn get_vanilla() {
+        let request = canonical_request("GET", "/", "", &headers(), "");
+        assert_eq!(
+            request,
+            "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\nhost;x-amz-date\ne3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let (scope, signature) = sign(SECRET, TIMESTAMP, "us-east-1", "service", &request);
+        assert_eq!(scope, "20150830/us-east-1/service/aws4_request");
+        assert_eq!(
+            signature,
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn get_vanilla_query_order_key_case() {
+        let query = canonical_query(&[("Param2", "value2"), ("Param1", "value1")]);
+        assert_eq!(query, "Param1=value1&Param2=value2");
+
+        let request = canonical_request("GET", "/", &query, &headers(), "");
+        let (_, signature) = sign(SECRET, TIMESTAMP, "us-east-1", "service", &request);
+        assert_eq!(
+            signature,
+            "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn public_zone_lookup() {
+        let resp = r#"<?xml version="1.0"?>
+<ListHostedZonesByNameResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><HostedZones><HostedZone><Id>/hostedzone/Z1PRIVATE</Id><Name>example.com.</Name><CallerReference>a</CallerReference><Config><PrivateZone>true</PrivateZone></Config><ResourceRecordSetCount>3</ResourceRecordSetCount></HostedZone><HostedZone><Id>/hostedzone/Z2PUBLIC</Id><Name>example.com.</Name><CallerReference>b</CallerReference><Config><Comment>main</Comment><PrivateZone>false</PrivateZone></Config><ResourceRecordSetCount>5</ResourceRecordSetCount></HostedZone><HostedZone><Id>/hostedzone/Z3NEXT</Id><Name>example.net.</Name><CallerReference>c</CallerReference><Config><PrivateZone>false</PrivateZone></Config><ResourceRecordSetCount>2</ResourceRecordSetCount></HostedZone></HostedZones><DNSName>example.com</DNSName><IsTruncated>false</IsTruncated><MaxItems>10</MaxItems></ListHostedZonesByNameResponse>"#;
+        assert_eq!(
+            public_zone_id(resp, "example.com").as_deref(),
+            Some("Z2PUBLIC")
+        );
+        assert_eq!(public_zone_id(resp, "example.org"), None);
+    }
+
+    #[test]
+    fn upsert_batch() {
+        let ips = Addresses {
+            ipv4: Some("192.0.2.1".parse().unwrap()),
+            ipv6: Some("2001:db8::1".parse().unwrap()),
+        };
+        assert_eq!(
+            change_batch("home.example.com", &ips, 300),
+            r#"<?xml version="1.0" encoding="UTF-8"?><ChangeResourceRecordSetsRequest xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><ChangeBatch><Comment>dnsupdate</Comment><Changes><Change><Action>UPSERT</Action><ResourceRecordSet><Name>home.example.com</Name><Type>A</Type><TTL>300</TTL><ResourceRecords><ResourceRecord><Value>192.0.2.1</Value></ResourceRecord></ResourceRecords></ResourceRecordSet></Change><Change><Action>UPSERT</Action><ResourceRecordSet><Name>home.example.com</Name><Type>AAAA</Type><TTL>300</TTL><ResourceRecords><ResourceRecord><Value>2001:db8::1</Value></ResourceRecord></ResourceRecords></ResourceRecordSet></Change></Changes></ChangeBatch></ChangeResourceRecordSetsRequest>"#
+        );
+
+        let ipv6_only = Addresses {
+            ipv4: None,
+            ipv6: ips.ipv6,
+        };
+        let batch = change_batch("home.example.com", &ipv6_only, 300);
+        assert!(!batch.contains("<Type>A</Type>"));
+        assert!(batch.contains("<Type>AAAA</Type>"));
+    }
+
+    #[test]
+    fn errors() {
+        let denied = r#"<?xml version="1.0"?>
+<ErrorResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><Error><Type>Sender</Type><Code>AccessDenied</Code><Message>User: arn:aws:iam::123456789012:user/dns is not authorized to perform: route53:ChangeResourceRecordSets</Message></Error><RequestId>a1b2</RequestId></ErrorResponse>"#;
+        assert_eq!(
+            error_message(denied),
+            "AccessDenied: User: arn:aws:iam::123456789012:user/dns is not authorized to perform: route53:ChangeResourceRecordSets"
+        );
+
+        let batch = r#"<?xml version="1.0"?>
+<InvalidChangeBatch xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><Messages><Message>RRSet with DNS name home.example.com. is not permitted in zone example.net.</Message><Message>Invalid Resource Record: &apos;FATAL problem&apos;</Message></Messages><RequestId>c3d4</RequestId></InvalidChangeBatch>"#;
+        assert_eq!(
+            error_message(batch),
+            "InvalidChangeBatch: RRSet with DNS name home.example.com. is not permitted in zone example.net., Invalid Resource Record: 'FATAL problem'"
+        );
+
+        assert_eq!(
+            error_message("<html>Bad gateway</html>"),
+            "unexpected response"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use super::{
+    dyndns2::{update_domains, Endpoint, Ipv6Param},
+    label, Service,
+};
+use crate::{ip::Addresses, state::ServiceState};
+use serde_derive::Deserialize;
+use std::error::Error;
+
+/// YDNS, whose update API answers with dyndns2 return codes.
+#[derive(Deserialize)]
+pub struct YDNSService {
+    name: Option<String>,
+    user: String,
+    password: String,
+    domains: Vec<String>,
+}
+
+impl Service for YDNSService {
+    fn label(&self) -> String {
+        label("YDNS", &self.name)
+    }
+
+    fn domains(&self) -> Vec<String> {
+        self.domains.clone()
+    }
+
+    fn update(&self, ips: &Addresses, state: &mut ServiceState) -> Result<(), Box<dyn Error>> {
+        let endpoint = Endpoint {
+            url: "https://ydns.io/api/v1/update/",
+            host_param: "host",
+            ip_param: "ip",
+            ipv6_param: Ipv6Param::Separate,
+            user: &self.user,
+            password: &self.password,
+        };
+        update_domains(&self.label(), &endpoint, &self.domains, ips, state)
+    }
+}
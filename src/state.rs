@@ -0,0 +1,105 @@
+use crate::ip::Addresses;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    net::{Ipv4Addr, Ipv6Addr},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// What was last pushed to each service, so that runs with an unchanged
+/// address make no provider calls at all.
+#[derive(Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    services: BTreeMap<String, ServiceState>,
+}
+
+/// Cached state of one service instance, by domain name.
+pub type ServiceState = BTreeMap<String, DomainState>;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct DomainState {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    /// Unix time of the last successful update.
+    pub last_success: Option<u64>,
+    /// Error the provider reported as permanent, e.g. bad credentials. The
+    /// domain is not updated again until a run with `--force`.
+    pub permanent_error: Option<String>,
+    /// Provider zone ID, for providers that need to look it up.
+    pub zone_id: Option<String>,
+    /// Provider record IDs, by record type.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub record_ids: BTreeMap<String, String>,
+}
+
+impl DomainState {
+    /// Why the domain must not be updated to these addresses, if it must
+    /// not: it already points to them, or it failed permanently.
+    pub fn skip_reason(&self, ips: &Addresses) -> Option<String> {
+        if let Some(e) = &self.permanent_error {
+            return Some(format!(
+                "Skipped after permanent error ({e}), run with --force to retry"
+            ));
+        }
+
+        match self.ipv4 == ips.ipv4 && self.ipv6 == ips.ipv6 {
+            true => Some("Unchanged".to_string()),
+            false => None,
+        }
+    }
+
+    /// Records a successful update to these addresses.
+    pub fn succeeded(&mut self, ips: &Addresses) {
+        self.ipv4 = ips.ipv4;
+        self.ipv6 = ips.ipv6;
+        self.last_success = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+    }
+
+    /// Forgets the cached provider IDs, so they are looked up again.
+    pub fn invalidate(&mut self) {
+        self.zone_id = None;
+        self.record_ids.clear();
+    }
+}
+
+impl State {
+    /// Reads the state file. A missing or unreadable file gives an empty
+    /// state, which only costs one run of full provider calls.
+    pub fn load(path: &Path) -> State {
+        if !path.exists() {
+            return State::default();
+        }
+
+        match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| toml::from_str(&s).map_err(|e| e.to_string()))
+        {
+            Ok(state) => state,
+            Err(e) => {
+                println!("Ignoring state file {}: {e}", path.display());
+                State::default()
+            }
+        }
+    }
+
+    /// Writes the state file, through a temporary file so that an
+    /// interrupted write never leaves a truncated state behind.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, toml::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Returns the state of a service instance, keyed by its label.
+    pub fn service(&mut self, label: &str) -> &mut ServiceState {
+        self.services.entry(label.to_string()).or_default()
+    }
+}
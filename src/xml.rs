@@ -0,0 +1,94 @@
+//! Just enough XML reading for the small, flat documents returned by routers
+//! and provider APIs.
+
+/// Returns the contents of every `tag` element, in document order. Namespace
+/// prefixes on the element name are ignored.
+pub fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let mut found = vec![];
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let open = &rest[..end];
+        rest = &rest[end + 1..];
+
+        let name = open.split_whitespace().next().unwrap_or("");
+        let local = name.rsplit(':').next().unwrap_or(name);
+        if local != tag || open.ends_with('/') {
+            continue;
+        }
+
+        let close = format!("</{name}>");
+        if let Some(end) = rest.find(&close) {
+            found.push(&rest[..end]);
+            rest = &rest[end + close.len()..];
+        }
+    }
+
+    found
+}
+
+/// Returns the trimmed, unescaped text of the first `tag` element.
+pub fn text(xml: &str, tag: &str) -> Option<String> {
+    elements(xml, tag).first().map(|text| unescape(text.trim()))
+}
+
+/// Resolves the predefined entities.
+pub fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Escapes text for use in element contents or attribute values.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_in_order_ignoring_prefixes() {
+        let xml = r#"<?xml version="1.0"?><s:Envelope><s:Body><u:Resp><Item a="1">one</Item><Item/><u:Item>two</u:Item></u:Resp></s:Body></s:Envelope>"#;
+        assert_eq!(elements(xml, "Item"), ["one", "two"]);
+        assert_eq!(elements(xml, "Resp").len(), 1);
+        assert!(elements(xml, "Missing").is_empty());
+    }
+
+    #[test]
+    fn nested_elements() {
+        let xml = "<Zones><Zone><Name>a.</Name></Zone><Zone><Name>b.</Name></Zone></Zones>";
+        let names: Vec<_> = elements(xml, "Zone")
+            .into_iter()
+            .filter_map(|zone| text(zone, "Name"))
+            .collect();
+        assert_eq!(names, ["a.", "b."]);
+    }
+
+    #[test]
+    fn text_is_trimmed_and_unescaped() {
+        assert_eq!(
+            text("<M>\n  a &lt;b&gt; &amp;amp; &quot;c&apos;\n</M>", "M").as_deref(),
+            Some("a <b> &amp; \"c'")
+        );
+        assert_eq!(text("<M></M>", "N"), None);
+    }
+
+    #[test]
+    fn escape_round_trips() {
+        let text = r#"a<b>&"c""#;
+        assert_eq!(escape(text), "a&lt;b&gt;&amp;&quot;c&quot;");
+        assert_eq!(unescape(&escape(text)), text);
+    }
+}